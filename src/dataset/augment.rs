@@ -0,0 +1,178 @@
+use std::fmt;
+
+use rand::prelude::*;
+
+//how wide the smoothing of the elastic displacement field is, in pixels
+const ELASTIC_SIGMA: f32 = 4.0;
+
+//one kind of distortion: how often it happens and how strong it is
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Transform {
+    pub chance: f32,
+    pub amount: f32,
+}
+
+//on-the-fly variants of the training images.
+//every image is redrawn each time it's seen, so no two epochs
+//see quite the same data. all chances start at 0, off.
+//  shift:   amount is the largest move in pixels
+//  rotate:  amount is the largest turn in degrees
+//  scale:   amount is the largest grow or shrink, 0.1 = 10%
+//  elastic: amount is the strength of the smoothed displacement
+//  noise:   amount is the standard deviation of the added noise
+#[derive(Debug, Clone, Default)]
+pub struct Augment {
+    pub shift: Transform,
+    pub rotate: Transform,
+    pub scale: Transform,
+    pub elastic: Transform,
+    pub noise: Transform,
+    //0 takes the images as square
+    pub width: usize,
+}
+
+impl Augment {
+    pub fn is_active(&self) -> bool {
+        [self.shift, self.rotate, self.scale, self.elastic, self.noise]
+            .iter()
+            .any(|transform| transform.chance > 0.0 && transform.amount != 0.0)
+    }
+
+    pub fn apply<R: Rng>(&self, image: &[f32], rng: &mut R) -> Vec<f32> {
+        let width = if self.width == 0 { (image.len() as f64).sqrt() as usize } else { self.width };
+        let height = image.len() / width.max(1);
+        let mut happens = |transform: Transform| transform.chance > 0.0 && rng.random::<f32>() < transform.chance;
+
+        //shift, rotate and scale fold into one resample
+        let (mut dx, mut dy, mut angle, mut zoom) = (0.0, 0.0, 0.0, 1.0);
+        let (shift, rotate, scale) = (happens(self.shift), happens(self.rotate), happens(self.scale));
+        let elastic = happens(self.elastic);
+        let noise = happens(self.noise);
+        if shift {
+            dx = rng.random_range(-1.0..=1.0) * self.shift.amount;
+            dy = rng.random_range(-1.0..=1.0) * self.shift.amount;
+        }
+        if rotate {
+            angle = (rng.random_range(-1.0..=1.0) * self.rotate.amount).to_radians();
+        }
+        if scale {
+            zoom = 1.0 + rng.random_range(-1.0..=1.0) * self.scale.amount;
+        }
+
+        let mut result = image.to_vec();
+        if shift || rotate || scale {
+            result = affine(&result, width, height, dx, dy, angle, zoom.max(0.1));
+        }
+        if elastic {
+            result = elastic_distort(&result, width, height, self.elastic.amount, rng);
+        }
+        if noise {
+            for pixel in result.iter_mut() {
+                *pixel = (*pixel + gaussian(rng) * self.noise.amount).clamp(0.0, 1.0);
+            }
+        }
+        result
+    }
+}
+
+impl fmt::Display for Augment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let named = [
+            ("shift", self.shift),
+            ("rotate", self.rotate),
+            ("scale", self.scale),
+            ("elastic", self.elastic),
+            ("noise", self.noise),
+        ];
+        let mut first = true;
+        for (name, transform) in named {
+            if transform.chance <= 0.0 { continue }
+            if !first { write!(f, ", ")? }
+            write!(f, "{name} {}@{}", transform.amount, transform.chance)?;
+            first = false;
+        }
+        if first { write!(f, "off")? }
+        Ok(())
+    }
+}
+
+//maps every output pixel back to where it came from and samples there
+fn affine(image: &[f32], width: usize, height: usize, dx: f32, dy: f32, angle: f32, zoom: f32) -> Vec<f32> {
+    let (cx, cy) = ((width as f32 - 1.0) / 2.0, (height as f32 - 1.0) / 2.0);
+    let (sin, cos) = angle.sin_cos();
+    let mut result = vec![0f32; image.len()];
+    for y in 0..height {
+        for x in 0..width {
+            let (px, py) = (x as f32 - cx - dx, y as f32 - cy - dy);
+            let sx = (cos * px + sin * py) / zoom + cx;
+            let sy = (-sin * px + cos * py) / zoom + cy;
+            result[y * width + x] = sample(image, width, height, sx, sy);
+        }
+    }
+    result
+}
+
+//random displacement per pixel, smoothed so neighbours move together
+fn elastic_distort<R: Rng>(image: &[f32], width: usize, height: usize, alpha: f32, rng: &mut R) -> Vec<f32> {
+    let field = |rng: &mut R| {
+        let noise: Vec<f32> = (0..image.len()).map(|_| rng.random_range(-1.0..=1.0)).collect();
+        blur(&noise, width, height, ELASTIC_SIGMA)
+    };
+    let field_x = field(rng);
+    let field_y = field(rng);
+    let mut result = vec![0f32; image.len()];
+    for y in 0..height {
+        for x in 0..width {
+            let i = y * width + x;
+            let sx = x as f32 + field_x[i] * alpha;
+            let sy = y as f32 + field_y[i] * alpha;
+            result[i] = sample(image, width, height, sx, sy);
+        }
+    }
+    result
+}
+
+//separable gaussian blur, rows then columns
+fn blur(values: &[f32], width: usize, height: usize, sigma: f32) -> Vec<f32> {
+    let radius = (sigma * 3.0).ceil() as isize;
+    let kernel: Vec<f32> = (-radius..=radius)
+        .map(|i| (-((i * i) as f32) / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let total: f32 = kernel.iter().sum();
+    let pass = |source: &[f32], horizontal: bool| {
+        let mut out = vec![0f32; source.len()];
+        for y in 0..height as isize {
+            for x in 0..width as isize {
+                let mut sum = 0.0;
+                for (k, weight) in (-radius..=radius).zip(&kernel) {
+                    let (sx, sy) = if horizontal { (x + k, y) } else { (x, y + k) };
+                    if sx < 0 || sy < 0 || sx >= width as isize || sy >= height as isize { continue }
+                    sum += source[sy as usize * width + sx as usize] * weight;
+                }
+                out[y as usize * width + x as usize] = sum / total;
+            }
+        }
+        out
+    };
+    pass(&pass(values, true), false)
+}
+
+//bilinear, anything outside the image is background
+fn sample(image: &[f32], width: usize, height: usize, x: f32, y: f32) -> f32 {
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+    let pixel = |px: f32, py: f32| {
+        if px < 0.0 || py < 0.0 || px >= width as f32 || py >= height as f32 { return 0.0 }
+        image[py as usize * width + px as usize]
+    };
+    let top = pixel(x0, y0) * (1.0 - fx) + pixel(x0 + 1.0, y0) * fx;
+    let bottom = pixel(x0, y0 + 1.0) * (1.0 - fx) + pixel(x0 + 1.0, y0 + 1.0) * fx;
+    top * (1.0 - fy) + bottom * fy
+}
+
+//Box-Muller, one standard normal value
+fn gaussian<R: Rng>(rng: &mut R) -> f32 {
+    let u1: f32 = rng.random::<f32>().max(f32::MIN_POSITIVE);
+    let u2: f32 = rng.random();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * std::f32::consts::PI * u2).cos()
+}
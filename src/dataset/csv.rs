@@ -0,0 +1,53 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Error, ErrorKind};
+use std::path::Path;
+
+use crate::dataset::SIDE;
+
+//one sample per row: label, then SIDE*SIDE pixels.
+//a header row is skipped if its label doesn't parse.
+//pixels written as bytes (0..255) are scaled down,
+//a file that never goes above 1.0 is taken as already normalized.
+pub fn read_csv(path: &Path) -> Result<(Vec<Vec<f32>>, Vec<u8>), Error> {
+    let file = File::open(path)?;
+    let rdr = BufReader::new(file);
+    let malformed = |line: usize, why: String| {
+        Error::new(ErrorKind::InvalidData, format!("{}:{line}: {why}", path.display()))
+    };
+
+    let mut data = Vec::new();
+    let mut labels = Vec::new();
+    let mut brightest = 0f32;
+    for (number, line) in rdr.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() { continue }
+        let mut fields = line.split(',').map(str::trim);
+        let label = fields.next().unwrap_or_default();
+        let label = match label.parse::<u8>() {
+            Ok(label) => label,
+            Err(_) if number == 0 => continue,
+            Err(_) => return Err(malformed(number + 1, format!("bad label {label:?}"))),
+        };
+        let mut image = Vec::with_capacity(SIDE * SIDE);
+        for field in fields {
+            let pixel = field.parse::<f32>()
+                .map_err(|_| malformed(number + 1, format!("bad pixel {field:?}")))?;
+            brightest = brightest.max(pixel);
+            image.push(pixel);
+        }
+        if image.len() != SIDE * SIDE {
+            return Err(malformed(number + 1, format!("{} pixels, expected {}", image.len(), SIDE * SIDE)))
+        }
+        data.push(image);
+        labels.push(label);
+    }
+    if brightest > 1.0 {
+        for image in data.iter_mut() {
+            for pixel in image.iter_mut() {
+                *pixel = (*pixel / 255.0).clamp(0.0, 1.0);
+            }
+        }
+    }
+    Ok((data, labels))
+}
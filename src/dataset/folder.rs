@@ -0,0 +1,58 @@
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+use image::imageops::{self, FilterType};
+
+use crate::dataset::SIDE;
+
+//  dir/
+//    0/ a.png b.png ...
+//    1/ ...
+//each sub folder is named by its class, every image inside
+//is grayscaled, squeezed to SIDE x SIDE and brought into [0,1].
+//anything that isn't a class folder or doesn't decode is skipped.
+pub fn read_folder(dir: &Path) -> Result<(Vec<Vec<f32>>, Vec<u8>), Error> {
+    let mut classes = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if !path.is_dir() { continue }
+        let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+        if let Ok(label) = name.parse::<u8>() {
+            classes.push((label, path));
+        }
+    }
+    if classes.is_empty() {
+        return Err(Error::new(ErrorKind::NotFound, format!("no class folders in {}", dir.display())))
+    }
+    //read_dir has no order, keep the data the same every load
+    classes.sort_by_key(|(label, _)| *label);
+
+    let mut data = Vec::new();
+    let mut labels = Vec::new();
+    for (label, path) in classes {
+        let mut files: Vec<_> = fs::read_dir(&path)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.is_file())
+            .collect();
+        files.sort();
+        for file in files {
+            match read_image(&file) {
+                Ok(image) => {
+                    data.push(image);
+                    labels.push(label);
+                },
+                Err(e) => println!(">skipping {}: {e}", file.display()),
+            }
+        }
+    }
+    Ok((data, labels))
+}
+
+fn read_image(path: &Path) -> Result<Vec<f32>, Error> {
+    let image = image::open(path)
+        .map_err(|e| Error::new(ErrorKind::InvalidData, e.to_string()))?
+        .to_luma8();
+    let image = imageops::resize(&image, SIDE as u32, SIDE as u32, FilterType::Triangle);
+    Ok(image.pixels().map(|pixel| pixel.0[0] as f32 / 255.0).collect())
+}
@@ -0,0 +1,81 @@
+use std::fs;
+use std::io::{Error, ErrorKind, Read};
+use std::path::Path;
+
+use flate2::read::GzDecoder;
+
+//the IDX format every MNIST lookalike ships in.
+//  magic: 0x00 0x00 <type> <dimension count>
+//  one big endian u32 per dimension
+//  the values, row after row
+//we only ever see unsigned bytes (type 0x08).
+const UNSIGNED_BYTE: u8 = 0x08;
+
+pub struct Idx {
+    pub dimensions: Vec<usize>,
+    pub values: Vec<u8>,
+}
+
+//plain or gzipped, the .gz ending decides
+pub fn read_idx(path: &Path) -> Result<Idx, Error> {
+    let mut bytes = fs::read(path)?;
+    if path.extension().is_some_and(|extension| extension == "gz") {
+        let mut unpacked = Vec::new();
+        GzDecoder::new(&bytes[..]).read_to_end(&mut unpacked)
+            .map_err(|e| Error::new(e.kind(), format!("{}: {e}", path.display())))?;
+        bytes = unpacked;
+    }
+    parse_idx(&bytes).map_err(|e| Error::new(e.kind(), format!("{}: {e}", path.display())))
+}
+
+pub fn parse_idx(bytes: &[u8]) -> Result<Idx, Error> {
+    let malformed = |why: &str| Error::new(ErrorKind::InvalidData, why.to_owned());
+    if bytes.len() < 4 || bytes[0] != 0 || bytes[1] != 0 {
+        return Err(malformed("not an idx file"))
+    }
+    if bytes[2] != UNSIGNED_BYTE {
+        return Err(malformed("only unsigned byte idx files are supported"))
+    }
+    let dimension_count = bytes[3] as usize;
+    let header = 4 + dimension_count * 4;
+    if bytes.len() < header {
+        return Err(malformed("idx header is cut short"))
+    }
+    let mut dimensions = Vec::new();
+    for d in 0..dimension_count {
+        let at = 4 + d * 4;
+        let size = u32::from_be_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]]);
+        dimensions.push(size as usize);
+    }
+    let expected: usize = dimensions.iter().product();
+    let values = &bytes[header..];
+    if values.len() != expected {
+        return Err(malformed(&format!(
+            "idx header promises {expected} values, file holds {}", values.len()
+        )))
+    }
+    Ok(Idx { dimensions, values: values.to_vec() })
+}
+
+//[count x height x width] bytes into one normalized image per row
+pub fn images_from_idx(idx: Idx) -> Result<(Vec<Vec<f32>>, usize, usize), Error> {
+    if idx.dimensions.len() != 3 {
+        return Err(Error::new(ErrorKind::InvalidData, "image idx needs 3 dimensions"))
+    }
+    let (height, width) = (idx.dimensions[1], idx.dimensions[2]);
+    if height * width == 0 {
+        return Err(Error::new(ErrorKind::InvalidData, "images have no pixels"))
+    }
+    let images = idx.values
+        .chunks_exact(height * width)
+        .map(|image| image.iter().map(|p| *p as f32 / 255.0).collect())
+        .collect();
+    Ok((images, width, height))
+}
+
+pub fn labels_from_idx(idx: Idx) -> Result<Vec<u8>, Error> {
+    if idx.dimensions.len() != 1 {
+        return Err(Error::new(ErrorKind::InvalidData, "label idx needs 1 dimension"))
+    }
+    Ok(idx.values)
+}
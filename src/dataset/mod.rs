@@ -0,0 +1,134 @@
+mod idx;
+mod csv;
+mod folder;
+mod augment;
+
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use crate::dataset::idx::*;
+use crate::dataset::csv::*;
+use crate::dataset::folder::*;
+pub use crate::dataset::augment::*;
+
+//the csv and image folder loaders bring everything to SIDE x SIDE
+pub const SIDE: usize = 28;
+
+//everything main hands to the systems, plus the shape of it.
+//the model is sized from here instead of assuming 28x28 digits.
+pub struct Dataset {
+    pub name: String,
+    pub train_data: Vec<Vec<f32>>,
+    pub train_labels: Vec<u8>,
+    pub test_data: Vec<Vec<f32>>,
+    pub test_labels: Vec<u8>,
+    pub width: usize,
+    pub height: usize,
+    pub classes: usize,
+}
+
+impl Dataset {
+    //any directory holding the four idx files of an MNIST lookalike.
+    //the names differ between sets, so they are found by what they contain:
+    //  train-images / train-labels
+    //  t10k-images / t10k-labels, or test-images / test-labels
+    //MNIST, Fashion-MNIST, KMNIST and the EMNIST splits all fit.
+    //each file may be gzipped as it comes from the download page.
+    //nothing here touches the network.
+    pub fn from_idx_dir(dir: &str) -> Result<Dataset, Error> {
+        let train_images = find_file(dir, &["train-images"])?;
+        let train_labels = find_file(dir, &["train-labels"])?;
+        let test_images = find_file(dir, &["t10k-images", "test-images"])?;
+        let test_labels = find_file(dir, &["t10k-labels", "test-labels"])?;
+
+        let (train_data, width, height) = images_from_idx(read_idx(&train_images)?)?;
+        let (test_data, test_width, test_height) = images_from_idx(read_idx(&test_images)?)?;
+        if (width, height) != (test_width, test_height) {
+            return Err(Error::new(ErrorKind::InvalidData, format!(
+                "train images are {width}x{height}, test images are {test_width}x{test_height}"
+            )))
+        }
+        let train_labels = labels_from_idx(read_idx(&train_labels)?)?;
+        let test_labels = labels_from_idx(read_idx(&test_labels)?)?;
+        check_counts("train", &train_data, &train_labels)?;
+        check_counts("test", &test_data, &test_labels)?;
+
+        let name = Path::new(dir)
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| dir.to_owned());
+        Ok(Dataset::new(name, train_data, train_labels, test_data, test_labels, width, height))
+    }
+
+    //label + SIDE*SIDE pixels per row, one file per split
+    pub fn from_csv(train: &str, test: &str) -> Result<Dataset, Error> {
+        let (train_data, train_labels) = read_csv(Path::new(train))?;
+        let (test_data, test_labels) = read_csv(Path::new(test))?;
+        let name = format!("csv {train}");
+        Ok(Dataset::new(name, train_data, train_labels, test_data, test_labels, SIDE, SIDE))
+    }
+    //one folder per split, holding one folder per class
+    pub fn from_folders(train: &str, test: &str) -> Result<Dataset, Error> {
+        let (train_data, train_labels) = read_folder(Path::new(train))?;
+        let (test_data, test_labels) = read_folder(Path::new(test))?;
+        let name = format!("folder {train}");
+        Ok(Dataset::new(name, train_data, train_labels, test_data, test_labels, SIDE, SIDE))
+    }
+
+    //the class count is read off the labels: highest label + 1
+    pub fn new(
+        name: String,
+        train_data: Vec<Vec<f32>>,
+        train_labels: Vec<u8>,
+        test_data: Vec<Vec<f32>>,
+        test_labels: Vec<u8>,
+        width: usize,
+        height: usize,
+        ) -> Dataset {
+        let highest = train_labels.iter().chain(&test_labels).max().copied().unwrap_or(0);
+        Dataset {
+            name,
+            train_data,
+            train_labels,
+            test_data,
+            test_labels,
+            width,
+            height,
+            classes: highest as usize + 1,
+        }
+    }
+    pub fn pixels(&self) -> usize {
+        self.width * self.height
+    }
+    pub fn describe(&self) {
+        println!("Data set: {}", self.name);
+        println!("Image size: {}x{}", self.width, self.height);
+        println!("Classes: {}", self.classes);
+        println!("Train data size: {}", self.train_data.len());
+        println!("Test data size: {}", self.test_data.len());
+        println!("Train labels size: {}", self.train_labels.len());
+        println!("Test labels size: {}", self.test_labels.len());
+    }
+}
+
+//every image needs exactly one label
+pub fn check_counts(split: &str, data: &[Vec<f32>], labels: &[u8]) -> Result<(), Error> {
+    if data.len() != labels.len() {
+        return Err(Error::new(ErrorKind::InvalidData, format!(
+            "{split} split has {} images but {} labels", data.len(), labels.len()
+        )))
+    }
+    Ok(())
+}
+
+fn find_file(dir: &str, patterns: &[&str]) -> Result<PathBuf, Error> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+        if patterns.iter().any(|pattern| name.contains(pattern)) {
+            return Ok(path)
+        }
+    }
+    Err(Error::new(ErrorKind::NotFound, format!("no {} file in {dir}", patterns.join(" or "))))
+}
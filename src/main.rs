@@ -1,29 +1,35 @@
 mod model;
+mod dataset;
 
 use crate::model::*;
+use crate::dataset::*;
 use std::{fmt::Debug, io::{self}};
-use mnist_reader::*;
 use tokio::*;
 
 #[tokio::main]
 fn main() {
-    let mut model = Model::startup();
+    let mut train_config = TrainConfig::default();
+    parse_args(&mut train_config);
     let _intermediate: Vec<Vec<Vec<f32>>> = Vec::new();
     
-    // read MNIST data
-    let mut mnist = MnistReader::new("mnist-data");
-    // download MNIST data
-    mnist.load().unwrap();
-    // print the size of the data
-    println!("Train data size: {}", mnist.train_data.len());
-    println!("Test data size: {}", mnist.test_data.len());
-    println!("Train labels size: {}", mnist.train_labels.len());
-    println!("Test labels size: {}", mnist.test_labels.len());
-
-    let train_data = mnist.train_data;
-    let train_labels = mnist.train_labels;
-    let test_data = mnist.test_data;
-    let test_labels = mnist.test_labels;
+    // read the data set from its local idx files.
+    // without it the model commands still work, only
+    // the ones that need images will refuse.
+    let mut dataset = match Dataset::from_idx_dir("mnist-data") {
+        Ok(dataset) => {
+            // print the shape and size of the data
+            dataset.describe();
+            Some(dataset)
+        },
+        Err(e) => {
+            println!("Data: missing ({e})");
+            println!("      use \"data load <dir>\" to read idx files from disk");
+            None
+        },
+    };
+    // a fresh model reads the images that are there,
+    // with nothing loaded it falls back to mnist's size
+    let mut model: Model = Model::startup(dataset.as_ref().map_or(28*28, |dataset| dataset.pixels()));
     
     let mut buf = String::new();
     loop {
@@ -33,14 +39,25 @@ fn main() {
         match buf.as_str() {
             "exit" => model.exit(),
             "help" => help(),
-            "new" => new_model_system(&mut model),
+            "new" => new_model_system(&mut model, &dataset),
+            "data" => data_status(&dataset),
+            other if other.starts_with("data ") => {
+                if let Some(loaded) = data_load_system(other) {
+                    loaded.describe();
+                    dataset = Some(loaded);
+                }
+            },
+            other if matches!(other.split_whitespace().next(), Some("freeze" | "unfreeze")) => freeze_system(&mut model, other),
             "save" => save_model_system(&model),
+            "pack" => pack_model_system(&model),
             "load" => load_model_system(&mut model),
-            "display" => data_view_system(&train_data, &train_labels),
+            "display" => if let Some(dataset) = require_data(&dataset) { data_view_system(dataset, &train_config.augment) },
             "show" => model.display_model(),
-            "shape" => shape_system(&mut model),
-            "test" => test_system(&model, &test_data, &test_labels),
-            "train" => train_system(&mut model, &train_data, &train_labels),
+            "shape" => shape_system(&mut model, &dataset),
+            "preprocess" => preprocess_system(&mut model, &dataset),
+            "test" => if let Some(dataset) = require_data(&dataset) { test_system(&mut model, dataset, &train_config) },
+            "train" => if let Some(dataset) = require_data(&dataset) { train_system(&mut model, dataset, &mut train_config) },
+            "prune" => if let Some(dataset) = require_data(&dataset) { prune_system(&mut model, dataset, &train_config) },
             "kwatz!" => (),
             other => println!(">{other} not a command"),
         }
@@ -50,10 +67,19 @@ fn main() {
 //this isn't as usable as I'd like
 //and nothing stops me from missing 
 //implementating one of them. 
-static COMMANDS: [&str; 21] = [
+static COMMANDS: [&str; 108] = [
     " help",
     " exit",
+    " data",
+    " data load <dir>",
+    " data csv <train> <test>",
+    " data folder <train> <test>",
     " show",
+    " freeze <l> <r>",
+    " freeze layer <l>",
+    " unfreeze",
+    " unfreeze <l> <r>",
+    " unfreeze layer <l>",
     " new",
     "   cancel",
     "   help",
@@ -63,6 +89,13 @@ static COMMANDS: [&str; 21] = [
     " load",
     "   cancel",
     "   help",
+    " pack",
+    "   f64",
+    "   f32",
+    "   f16",
+    "   bf16",
+    "   int8",
+    "   cancel",
     " display",
     "   cancel",
     "   help",
@@ -70,8 +103,79 @@ static COMMANDS: [&str; 21] = [
     "   cap",
     "   add ring",
     "     end",
+    "   add conv",
+    "     end",
+    "   add pool",
+    "     end",
+    "   add identity",
+    "     end",
+    "   regularize",
+    "   normalize",
+    "   merge",
     "   cancel",
     "   help",
+    " preprocess",
+    "   invert",
+    "   binarize",
+    "   standardize",
+    "   raw",
+    "   cancel",
+    " test",
+    "   test all",
+    "   bench",
+    "   check kernels",
+    "   quantize",
+    "   dequantize",
+    "   gradcheck",
+    "   gradcheck random",
+    "   ensemble",
+    "     end",
+    "     vote",
+    "     average",
+    "     weighted",
+    "     predict",
+    "     cancel",
+    "   cancel",
+    " prune",
+    "   global",
+    "   ring",
+    "   neurons",
+    "     weight",
+    "     activation",
+    "   cancel",
+    " train",
+    "   cap",
+    "   run",
+    "   epochs",
+    "   batch",
+    "   rate",
+    "   threads",
+    "   seed",
+    "   validation",
+    "   patience",
+    "   best",
+    "   augment",
+    "     shift",
+    "     rotate",
+    "     scale",
+    "     elastic",
+    "     noise",
+    "     off",
+    "     cancel",
+    "   distill",
+    "     temperature",
+    "     mix",
+    "     run",
+    "     cancel",
+    "   grow",
+    "   schedule",
+    "     constant",
+    "     step",
+    "     exponential",
+    "     cosine",
+    "     onecycle",
+    "     cancel",
+    "   cancel",
 ];
 fn save_model_system(model: &Model) {
     println!("(\"cancel\" to abort) \nfilename:");
@@ -109,13 +213,35 @@ fn load_model_system(model: &mut Model)  {
         }
     }
 }
+fn pack_model_system(model: &Model) {
+    println!("(\"cancel\" to abort) \nstorage (f64, f32, f16, bf16, int8):");
+    let input = user_input();
+    let storage = match input.as_str() {
+        "cancel" => { println!(">canceling pack operation"); return },
+        other => match other.parse::<Storage>() {
+            Ok(storage) => storage,
+            Err(_) => { println!(">{other} is not a storage type"); return },
+        },
+    };
+    println!("filename:");
+    let newbuf = user_input();
+    match newbuf.as_str() {
+        "cancel" => println!(">canceling pack operation"),
+        _ => {
+            match model.save_packed(&newbuf, storage) {
+                Ok(size) => println!(">model packed as {storage:?} to file {newbuf}.json ({size} bytes)"),
+                Err(e) => println!(">failed to pack model: {e}"),
+            }
+        }
+    }
+}
 fn help() {
     println!(">Command list:");
     for str in COMMANDS {
         println!("{str}");
     }
 }
-fn new_model_system(model: &mut Model) {
+fn new_model_system(model: &mut Model, dataset: &Option<Dataset>) {
     
     println!("New Model System");
     let depth;
@@ -154,11 +280,22 @@ fn new_model_system(model: &mut Model) {
             }
         }
     }
-    *model = Model::new(depth, width);
+    //without data the new model keeps the old one's input size
+    let input_size = match dataset {
+        Some(dataset) => dataset.pixels(),
+        None => model.input_size(),
+    };
+    *model = Model::new(depth, width, input_size);
     model.display_model();
     println!(">new model created");
 }
-fn data_view_system(data: &Vec<Vec<f32>>, labels: &Vec<u8>) {
+fn data_view_system(dataset: &Dataset, augment: &Augment) {
+    let data = &dataset.train_data;
+    let labels = &dataset.train_labels;
+    let (width, height) = (dataset.width, dataset.height);
+    let mut augment = augment.clone();
+    augment.width = width;
+    let mut rng = rand::rng();
     loop {
         println!("Data View System");
         println!("enter index");
@@ -174,27 +311,20 @@ fn data_view_system(data: &Vec<Vec<f32>>, labels: &Vec<u8>) {
                     println!(">index {} exceeds maximum of {}", index, data.len() - 1);
                     continue 
                     }
-                let data = &data[index];
-                for n in (0..=26).step_by(2) {
-                    let mut line = String::new();
-                    for i in 0..28 {
-                        if data[i + (n*28)] > 0.5 {
-                            if data[i + (n*28+28)] > 0.5 {
-                                line.push('█');
-                            } else {
-                                line.push('▀');
-                            }
-                        } else {
-                            if data[i + (n*28+28)] > 0.5 {
-                                line.push('▄');
-                            } else {
-                                line.push(' ');
-                            }
-                        }
+                let original = render_image(&data[index], width, height);
+                //the augmented draw sits to the right, fresh each time
+                let variant = if augment.is_active() {
+                    render_image(&augment.apply(&data[index], &mut rng), width, height)
+                } else {
+                    Vec::new()
+                };
+                for (n, mut line) in original.into_iter().enumerate() {
+                    if let Some(other) = variant.get(n) {
+                        line.push_str("  ");
+                        line.push_str(other);
                     }
                     if n == 0 {
-                        let ch = (labels[index] + b'0') as char;
-                        line.push(ch);
+                        line.push_str(&labels[index].to_string());
                     }
                     println!("{line}");
                 }
@@ -202,6 +332,32 @@ fn data_view_system(data: &Vec<Vec<f32>>, labels: &Vec<u8>) {
         }
     }
 }
+//two pixel rows to a line of half blocks
+fn render_image(data: &[f32], width: usize, height: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    for n in (0..height).step_by(2) {
+        let mut line = String::new();
+        for i in 0..width {
+            //odd heights leave the last half row empty
+            let below = n + 1 < height && data[i + (n*width+width)] > 0.5;
+            if data[i + (n*width)] > 0.5 {
+                if below {
+                    line.push('█');
+                } else {
+                    line.push('▀');
+                }
+            } else {
+                if below {
+                    line.push('▄');
+                } else {
+                    line.push(' ');
+                }
+            }
+        }
+        lines.push(line);
+    }
+    lines
+}
 
 fn user_input() -> String {
     println!("Command: ");
@@ -211,27 +367,238 @@ fn user_input() -> String {
     newbuf
 }
 
-fn train_system(model: &mut Model, _data: &Vec<Vec<f32>>, _labels: &Vec<u8>) {
+fn train_system(model: &mut Model, dataset: &Dataset, config: &mut TrainConfig) {
     loop {
         println!("Train System");
+        println!("{config}");
+        println!("{}", model.progress());
         let input = user_input();
         match input.as_str() {
-            "cap" => model.cap(),
+            "cap" => model.cap(dataset.classes),
+            "run" => {
+                if !fits(model, dataset) { continue }
+                config.augment.width = dataset.width;
+                model.train(&dataset.train_data, &dataset.train_labels, config)
+            },
+            "epochs" => if let Some(n) = ask_number("Epochs: ") { config.epochs = n },
+            "batch" => if let Some(n) = ask_number("Batch size: ") { config.batch_size = n },
+            "rate" => if let Some(n) = ask_number("Learning rate: ") { config.learning_rate = n },
+            "threads" => if let Some(n) = ask_number("Thread count: ") { config.threads = n },
+            "seed" => if let Some(n) = ask_number("Seed: ") { config.seed = n },
+            "validation" => if let Some(n) = ask_number("Validation share (0.0 - 1.0): ") { config.validation = n },
+            "patience" => if let Some(n) = ask_number("Patience in epochs (0 = never stop): ") { config.patience = n },
+            "augment" => augment_system(&mut config.augment),
+            "schedule" => schedule_system(model),
+            "distill" => distill_system(model, dataset, config),
+            "grow" => {
+                if !fits(model, dataset) { continue }
+                let Some(rounds) = ask_number("Growth rounds: ") else { continue };
+                let Some(epochs) = ask_number("Epochs per round: ") else { continue };
+                let Some(neurons) = ask_number("Neurons per new ring: ") else { continue };
+                //the layer is only chosen while growing, so the children are a rule
+                println!("Children of each new ring, copy (the busiest ring's) or below (every ring of the layer below): ");
+                let input = user_input();
+                let Ok(from) = input.parse::<GrowFrom>() else { println!(">{input} is not copy or below"); continue };
+                config.augment.width = dataset.width;
+                model.grow(
+                    &dataset.train_data,
+                    &dataset.train_labels,
+                    &dataset.test_data,
+                    &dataset.test_labels,
+                    config,
+                    &Growth { rounds, epochs, neurons, from },
+                );
+            },
+            "best" => {
+                println!("Best model filename, or none to not save it: ");
+                match user_input().as_str() {
+                    "cancel" => (),
+                    "none" => config.best_file = None,
+                    name => config.best_file = Some(String::from(name)),
+                }
+            },
             "exit" => return,
             "cancel" => return,
-            other => { println!(">{other} is not a shape command"); continue }
+            other => { println!(">{other} is not a train command"); continue }
+        }
+    }
+}
+fn augment_system(augment: &mut Augment) {
+    loop {
+        println!("Augment System");
+        println!("{augment}");
+        let input = user_input();
+        let transform = match input.as_str() {
+            "shift" => &mut augment.shift,
+            "rotate" => &mut augment.rotate,
+            "scale" => &mut augment.scale,
+            "elastic" => &mut augment.elastic,
+            "noise" => &mut augment.noise,
+            "off" => { *augment = Augment::default(); continue },
+            "exit" => return,
+            "cancel" => return,
+            other => { println!(">{other} is not an augment command"); continue }
+        };
+        let Some(chance) = ask_number::<f32>("Chance per image (0.0 - 1.0): ") else { continue };
+        let Some(amount) = ask_number::<f32>("Amount (pixels, degrees, fraction, strength or deviation): ") else { continue };
+        *transform = Transform { chance: chance.clamp(0.0, 1.0), amount };
+    }
+}
+//train against a larger saved model's softened answers as well as the labels
+fn distill_system(model: &mut Model, dataset: &Dataset, config: &mut TrainConfig) {
+    loop {
+        println!("Distill System");
+        println!("{}", config.distill);
+        let input = user_input();
+        match input.as_str() {
+            "temperature" => if let Some(n) = ask_number::<f32>("Temperature: ") { config.distill.temperature = n.max(f32::EPSILON) },
+            "mix" => if let Some(n) = ask_number::<f32>("Soft share of the loss (0.0 - 1.0): ") { config.distill.soft = n.clamp(0.0, 1.0) },
+            "run" => {
+                if !fits(model, dataset) { continue }
+                println!("Teacher filename: ");
+                let name = user_input();
+                if name == "cancel" { continue }
+                let teacher = match Model::load(&name) {
+                    Ok(teacher) => teacher,
+                    Err(e) => { println!(">failed to load {name}.json: {e}"); continue },
+                };
+                if !fits(&teacher, dataset) { continue }
+                config.augment.width = dataset.width;
+                println!(">teacher accuracy {:.2}%", teacher.accuracy(&dataset.test_data, &dataset.test_labels) * 100.0);
+                model.distill(&teacher, &dataset.train_data, &dataset.train_labels, config);
+                println!(">student accuracy {:.2}%", model.accuracy(&dataset.test_data, &dataset.test_labels) * 100.0);
+            },
+            "exit" => return,
+            "cancel" => return,
+            other => { println!(">{other} is not a distill command"); continue }
         }
     }
-    //one_hot encoding. 
-        let mut one_hot = Vec::new();
-        for label in _labels {
-            let mut e = [0.0;10];
-            let i = *label as usize;
-            if i >= 10 {panic!("out of bounds digit")}
-            e[i] = 1.00;
-            one_hot.push(e);
+}
+//the schedule is kept in the model, so picking one restarts it at epoch 0
+fn schedule_system(model: &mut Model) {
+    println!("Schedule System");
+    println!("constant, step, exponential, cosine or onecycle");
+    let schedule = match user_input().as_str() {
+        "constant" => Schedule::Constant,
+        "step" => {
+            let Some(every) = ask_number("Epochs per step: ") else { return };
+            let Some(factor) = ask_number("Factor per step: ") else { return };
+            Schedule::Step { every, factor }
+        },
+        "exponential" => {
+            let Some(factor) = ask_number("Factor per epoch: ") else { return };
+            Schedule::Exponential { factor }
+        },
+        "cosine" => {
+            let Some(period) = ask_number("Epochs between restarts: ") else { return };
+            let Some(floor) = ask_number("Lowest factor (0.0 - 1.0): ") else { return };
+            Schedule::Cosine { period, floor }
+        },
+        "onecycle" => {
+            let Some(length) = ask_number("Epochs in the cycle: ") else { return };
+            Schedule::OneCycle { length }
+        },
+        "exit" => return,
+        "cancel" => return,
+        other => { println!(">{other} is not a schedule"); return }
+    };
+    let Some(warmup) = ask_number("Warmup epochs (0 for none): ") else { return };
+    model.set_schedule(schedule, warmup);
+    println!(">{}", model.progress());
+}
+
+//zero the smallest weights, then optionally train the rest to make up for them
+fn prune_system(model: &mut Model, dataset: &Dataset, config: &TrainConfig) {
+    if !fits(model, dataset) { return }
+    println!("Prune System");
+    println!("global, ring or neurons");
+    let input = user_input();
+    let scope = match input.as_str() {
+        "exit" => return,
+        "cancel" => return,
+        "neurons" => { neuron_prune_system(model, dataset); return },
+        other => match other.parse::<PruneScope>() {
+            Ok(scope) => scope,
+            Err(()) => { println!(">{other} is not a prune scope"); return },
+        },
+    };
+    let Some(percent) = ask_number::<f32>("Percent of weights to prune (0 - 100): ") else { return };
+    let Some(epochs) = ask_number::<usize>("Fine-tune epochs (0 for none): ") else { return };
+
+    let before = model.accuracy(&dataset.test_data, &dataset.test_labels);
+    model.prune(percent / 100.0, scope);
+    let pruned = model.accuracy(&dataset.test_data, &dataset.test_labels);
+    println!(">accuracy {:.2}% -> {:.2}% pruned", before * 100.0, pruned * 100.0);
+    if epochs == 0 { return }
+
+    let mut config = TrainConfig { epochs, ..config.clone() };
+    config.augment.width = dataset.width;
+    //the pruned weights are masked, they stay at 0 while the rest train
+    model.train(&dataset.train_data, &dataset.train_labels, &config);
+    let tuned = model.accuracy(&dataset.test_data, &dataset.test_labels);
+    println!(">accuracy {:.2}% -> {:.2}% fine-tuned", pruned * 100.0, tuned * 100.0);
+}
+
+//whole neurons out of every hidden dense ring, the model comes out smaller
+fn neuron_prune_system(model: &mut Model, dataset: &Dataset) {
+    println!("rank by weight or activation");
+    let input = user_input();
+    let rank = match input.as_str() {
+        "exit" => return,
+        "cancel" => return,
+        other => match other.parse::<NeuronRank>() {
+            Ok(rank) => rank,
+            Err(()) => { println!(">{other} is not a neuron rank"); return },
+        },
+    };
+    let count = match rank {
+        NeuronRank::Activation => {
+            let Some(count) = ask_number::<usize>("Sample images: ") else { return };
+            count.min(dataset.train_data.len())
+        },
+        NeuronRank::Weight => 0,
+    };
+    let Some(percent) = ask_number::<f32>("Percent of each ring's neurons to remove (0 - 100): ") else { return };
+
+    let before = model.accuracy(&dataset.test_data, &dataset.test_labels);
+    model.prune_neurons(percent / 100.0, rank, &dataset.train_data[..count]);
+    let after = model.accuracy(&dataset.test_data, &dataset.test_labels);
+    println!(">accuracy {:.2}% -> {:.2}%", before * 100.0, after * 100.0);
+}
+
+fn ask_number<T: std::str::FromStr>(prompt: &str) -> Option<T> {
+    loop {
+        println!("{prompt}");
+        let input = user_input();
+        match input.as_str() {
+            "exit" => return None,
+            "cancel" => return None,
+            other => {
+                if let Ok(num) = other.parse::<T>() {
+                    return Some(num)
+                } else {
+                    println!(">malformed number: {other}");
+                    continue
+                }
+            }
         }
-        println!("{:?}", one_hot[0]);
+    }
+}
+
+//--threads 8
+fn parse_args(config: &mut TrainConfig) {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--threads" => {
+                match args.next().map(|n| n.parse::<usize>()) {
+                    Some(Ok(n)) => config.threads = n,
+                    _ => println!(">--threads needs a number"),
+                }
+            },
+            other => println!(">unknown argument {other}"),
+        }
+    }
 }
 
 fn child_system(ring_layer: usize) -> Option<Vec<Child>> {
@@ -299,6 +666,95 @@ fn add_ring_system(model: &mut Model){
         }
     }
 }
+//a ring that slides a kernel over its children as feature maps
+fn add_conv_system(model: &mut Model) {
+    let Some(layer) = ask_number::<usize>("Layer number: ") else { return };
+    if layer == 0 { println!(">cannot add to input layer"); return }
+    let Some(kernel) = ask_number("Kernel size: ") else { return };
+    let Some(stride) = ask_number("Stride: ") else { return };
+    let Some(padding) = ask_number("Padding: ") else { return };
+    let Some(channels) = ask_number("Channels: ") else { return };
+    let Some(children) = child_system(layer) else { println!(">no children to form ring"); return };
+    model.add_conv_ring(layer, kernel, stride, padding, channels, children);
+    model.display_model();
+}
+//max or average over windows of the children's feature maps, no weights
+fn add_pool_system(model: &mut Model) {
+    let Some(layer) = ask_number::<usize>("Layer number: ") else { return };
+    if layer == 0 { println!(">cannot add to input layer"); return }
+    println!("max or avg: ");
+    let input = user_input();
+    let Ok(mode) = input.parse::<PoolMode>() else { println!(">{input} is not a pooling mode"); return };
+    let Some(window) = ask_number("Window size: ") else { return };
+    let Some(stride) = ask_number("Stride: ") else { return };
+    let Some(children) = child_system(layer) else { println!(">no children to form ring"); return };
+    model.add_pool_ring(layer, mode, window, stride, children);
+    model.display_model();
+}
+//passes its children on with no weights, to skip them past layers
+fn add_identity_system(model: &mut Model) {
+    let Some(layer) = ask_number::<usize>("Layer number: ") else { return };
+    if layer == 0 { println!(">cannot add to input layer"); return }
+    println!("concat, sum, mean or max: ");
+    let input = user_input();
+    let Ok(merge) = input.parse::<Merge>() else { println!(">{input} is not a merge"); return };
+    let Some(children) = child_system(layer) else { println!(">no children to form ring"); return };
+    model.add_identity_ring(layer, merge, children);
+    model.display_model();
+}
+//penalties, dropout and max norm for one ring, 0 turns each off
+//"<layer> <ring>", None on cancel or a typo
+fn ask_ring_double() -> Option<(usize, usize)> {
+    println!("Ring double: ");
+    let input = user_input();
+    if input == "cancel" || input == "exit" { return None }
+    let mut double = input.split(' ').map(|part| part.parse::<usize>());
+    match (double.next(), double.next(), double.next()) {
+        (Some(Ok(layer)), Some(Ok(ring)), None) => Some((layer, ring)),
+        _ => { println!(">malformed ring: {input}"); None }
+    }
+}
+fn regularize_system(model: &mut Model) {
+    let Some((layer, ring)) = ask_ring_double() else { return };
+    let Some(current) = model.regularize(layer, ring) else { println!(">no ring at [{layer}][{ring}]"); return };
+    println!("{current}");
+    let Some(l1) = ask_number("L1 penalty: ") else { return };
+    let Some(l2) = ask_number("L2 penalty: ") else { return };
+    let Some(dropout) = ask_number("Dropout (0.0 - 0.95): ") else { return };
+    let Some(max_norm) = ask_number("Max norm: ") else { return };
+    model.set_regularize(layer, ring, Regularize { l1, l2, dropout, max_norm });
+    if let Some(regularize) = model.regularize(layer, ring) { println!(">[{layer}][{ring}] {regularize}") }
+}
+//batch norm, layer norm or none for one ring
+fn normalize_system(model: &mut Model) {
+    let Some((layer, ring)) = ask_ring_double() else { return };
+    let Some(current) = model.norm(layer, ring) else { println!(">no ring at [{layer}][{ring}]"); return };
+    println!("{current}");
+    println!("batch, layer or none: ");
+    let input = user_input();
+    match input.parse::<NormKind>() {
+        Ok(kind) => {
+            model.set_norm(layer, ring, kind);
+            println!(">[{layer}][{ring}] {kind}");
+        },
+        Err(()) => println!(">{input} is not a normalization"),
+    }
+}
+//how a ring lays its children together, sum makes a residual shortcut
+fn merge_system(model: &mut Model) {
+    let Some((layer, ring)) = ask_ring_double() else { return };
+    let Some(current) = model.merge(layer, ring) else { println!(">no ring at [{layer}][{ring}]"); return };
+    println!("{current}");
+    println!("concat, sum, mean or max: ");
+    let input = user_input();
+    match input.parse::<Merge>() {
+        Ok(merge) => {
+            model.set_merge(layer, ring, merge);
+            if let Some(merge) = model.merge(layer, ring) { println!(">[{layer}][{ring}] {merge}") }
+        },
+        Err(()) => println!(">{input} is not a merge"),
+    }
+}
 
 fn ask_neuron_count() -> Option<usize> {
     loop {
@@ -319,33 +775,220 @@ fn ask_neuron_count() -> Option<usize> {
     }
 }
 
-fn shape_system(model: &mut Model) {
+fn shape_system(model: &mut Model, dataset: &Option<Dataset>) {
     loop {
         println!("Shape System");
         let input = user_input();
         match input.as_str() {
-            "cap" => model.cap(),
+            "cap" => if let Some(dataset) = require_data(dataset) { model.cap(dataset.classes) },
             "add ring" => add_ring_system(model),
+            "add conv" => add_conv_system(model),
+            "add pool" => add_pool_system(model),
+            "add identity" => add_identity_system(model),
+            "regularize" => regularize_system(model),
+            "normalize" => normalize_system(model),
+            "merge" => merge_system(model),
             "exit" => return,
             "cancel" => return,
             other => { println!(">{other} is not a shape command"); continue }
         }
     }
 }
+//the settings travel with the model, test and train both go through them
+fn preprocess_system(model: &mut Model, dataset: &Option<Dataset>) {
+    loop {
+        println!("Preprocess System");
+        println!("{}", model.preprocess());
+        let input = user_input();
+        match input.as_str() {
+            "invert" => {
+                let invert = !model.preprocess().invert;
+                model.set_invert(invert);
+            },
+            "binarize" => {
+                let threshold = ask_number::<f32>("Threshold (0.5 like display, negative for off): ");
+                if let Some(threshold) = threshold {
+                    model.set_threshold(if threshold < 0.0 { None } else { Some(threshold) });
+                }
+            },
+            "standardize" => if let Some(dataset) = require_data(dataset) { model.standardize(&dataset.train_data) },
+            "raw" => {
+                model.set_invert(false);
+                model.set_threshold(None);
+                model.unstandardize();
+            },
+            "exit" => return,
+            "cancel" => return,
+            other => { println!(">{other} is not a preprocess command"); continue }
+        }
+    }
+}
 
-fn test_system(model: &Model, test_data: &Vec<Vec<f32>>, test_labels: &Vec<u8>) {
+fn test_system(model: &mut Model, dataset: &Dataset, config: &TrainConfig) {
+    let train_data = &dataset.train_data;
+    let test_data = &dataset.test_data;
+    let test_labels = &dataset.test_labels;
+    //the commands that push data through the model need it to fit
+    let fitted = fits(model, dataset);
     loop {
         println!("Test System");
+        if model.is_quantized() { println!(">running int8") }
         let input = user_input();
         match input.as_str() {
             "cancel" => return,
             "exit" => return,
-            "test all" => model.test(&test_data, &test_labels),
+            "test all" if fitted => model.test(test_data, test_labels),
+            "bench" if fitted => model.bench(test_data),
+            "check kernels" => { check_kernels(); },
+            "quantize" if fitted => {
+                if let Some(count) = ask_number::<usize>("Calibration images: ") {
+                    let count = count.min(train_data.len());
+                    model.quantize(&train_data[..count]);
+                }
+            },
+            "dequantize" => model.dequantize(),
+            "ensemble" => ensemble_system(dataset),
+            "gradcheck" if fitted => {
+                let count = test_data.len().min(8);
+                let report = gradcheck(model, &test_data[..count], &test_labels[..count], GRADCHECK_TOLERANCE, config.seed);
+                print_gradcheck(&report, GRADCHECK_TOLERANCE);
+            },
+            "gradcheck random" => {
+                let small = gradcheck_model(config.seed);
+                let (images, labels) = gradcheck_batch(8, 28*28, 10, config.seed);
+                let report = gradcheck(&small, &images, &labels, GRADCHECK_TOLERANCE, config.seed);
+                print_gradcheck(&report, GRADCHECK_TOLERANCE);
+            },
             _ => continue,
         }
     }
 }
 
+//several saved models answering the test images together
+fn ensemble_system(dataset: &Dataset) {
+    println!("Ensemble System");
+    println!("member filenames, \"end\" when done");
+    let mut names = Vec::new();
+    loop {
+        let input = user_input();
+        match input.as_str() {
+            "end" => break,
+            "exit" => return,
+            "cancel" => return,
+            name => names.push(name.to_owned()),
+        }
+    }
+    let mut ensemble: Ensemble = match Ensemble::load(&names) {
+        Ok(ensemble) => ensemble,
+        Err(e) => { println!(">failed to load the ensemble: {e}"); return },
+    };
+    if ensemble.members().iter().any(|(_, model)| !fits(model, dataset)) { return }
+    let mut tuned = false;
+    loop {
+        println!("vote, average, weighted or predict");
+        let input = user_input();
+        match input.as_str() {
+            "exit" => return,
+            "cancel" => return,
+            "predict" => {
+                let Some(i) = ask_number::<usize>("Test image: ") else { continue };
+                let Some(image) = dataset.test_data.get(i) else { println!(">no test image {i}"); continue };
+                for (name, model) in ensemble.members() {
+                    println!(">{name}: {}", model.projection(image));
+                }
+                for vote in [Vote::Majority, Vote::Average, Vote::Weighted] {
+                    if vote == Vote::Weighted && !tuned { continue }
+                    println!(">ensemble {vote:?}: {}", ensemble.predict(image, vote));
+                }
+                println!(">label: {}", dataset.test_labels[i]);
+            },
+            other => match other.parse::<Vote>() {
+                Ok(vote) => {
+                    //tuned on the training images every member held back
+                    if vote == Vote::Weighted && !tuned {
+                        let held_back = match ensemble.held_back(&dataset.train_labels) {
+                            Ok(held_back) => held_back,
+                            Err(e) => { println!(">can't tune: {e}"); continue },
+                        };
+                        println!(">tuning on the {} images the members held back", held_back.len());
+                        let images: Vec<&Vec<f32>> = held_back.iter().map(|&i| &dataset.train_data[i]).collect();
+                        let labels: Vec<u8> = held_back.iter().map(|&i| dataset.train_labels[i]).collect();
+                        ensemble.tune(&images, &labels);
+                        tuned = true;
+                    }
+                    ensemble.test(&dataset.test_data, &dataset.test_labels, vote);
+                },
+                Err(()) => println!(">{other} is not an ensemble command"),
+            },
+        }
+    }
+}
+
+//frozen rings keep their weights while the rest trains
+fn freeze_system(model: &mut Model, command: &str) {
+    let words: Vec<&str> = command.split_whitespace().collect();
+    let trainable = words[0] == "unfreeze";
+    let numbers: Vec<Option<usize>> = words[1..].iter().map(|word| word.parse().ok()).collect();
+    match (&words[1..], numbers.as_slice()) {
+        ([], _) if trainable => { model.unfreeze_all(); println!(">every ring trains") },
+        (["layer", _], [_, Some(layer)]) => {
+            model.set_layer_trainable(*layer, trainable);
+        },
+        ([_, _], [Some(layer), Some(ring)]) => {
+            model.set_trainable(*layer, *ring, trainable);
+        },
+        _ => { println!(">{command} is not a freeze command"); return },
+    }
+    println!(">{} frozen rings", model.frozen_count());
+}
+
+//  data load <dir>
+//  data csv <train.csv> <test.csv>
+//  data folder <train dir> <test dir>
+fn data_load_system(command: &str) -> Option<Dataset> {
+    let words: Vec<&str> = command.split_whitespace().collect();
+    let result = match words.as_slice() {
+        ["data", "load", dir] => Dataset::from_idx_dir(dir),
+        ["data", "csv", train, test] => Dataset::from_csv(train, test),
+        ["data", "folder", train, test] => Dataset::from_folders(train, test),
+        _ => { println!(">{command} is not a data command"); return None },
+    };
+    match result {
+        Ok(dataset) => Some(dataset),
+        Err(e) => { println!(">failed to load data: {e}"); None },
+    }
+}
+
+fn data_status(dataset: &Option<Dataset>) {
+    match dataset {
+        Some(dataset) => dataset.describe(),
+        None => println!("Data: missing"),
+    }
+}
+
+//for the commands that can do nothing without images
+fn require_data(dataset: &Option<Dataset>) -> Option<&Dataset> {
+    if dataset.is_none() {
+        println!(">no data loaded, use \"data load <dir>\"");
+    }
+    dataset.as_ref()
+}
+
+//the model has to take the images and answer in the classes of the data
+fn fits(model: &Model, dataset: &Dataset) -> bool {
+    if model.input_size() != dataset.pixels() {
+        println!(">model takes {} pixels, {} images have {}", model.input_size(), dataset.name, dataset.pixels());
+        return false
+    }
+    match model.class_count() {
+        Some(classes) if classes < dataset.classes => {
+            println!(">model answers {classes} classes, {} has {}", dataset.name, dataset.classes);
+            false
+        },
+        _ => true,
+    }
+}
+
 fn echo<T: Debug>(input: &T) {
     println!("{input:?}");
 }
@@ -0,0 +1,46 @@
+use std::fmt;
+
+//answers against labels, one row per label, one column per answer
+#[derive(Debug, Clone)]
+pub struct Confusion {
+    classes: usize,
+    counts: Vec<usize>,
+    correct: usize,
+    incorrect: usize,
+}
+
+impl Confusion {
+    pub fn new(classes: usize) -> Confusion {
+        Confusion { classes, counts: vec![0; classes * classes], correct: 0, incorrect: 0 }
+    }
+    //an answer outside the classes, like a malformed model's, is only counted wrong
+    pub fn add(&mut self, answer: u8, result: usize) {
+        let answer = answer as usize;
+        if result == answer { self.correct += 1 } else { self.incorrect += 1 }
+        if answer < self.classes && result < self.classes {
+            self.counts[answer * self.classes + result] += 1;
+        }
+    }
+    pub fn accuracy(&self) -> f32 {
+        self.correct as f32 / (self.correct + self.incorrect).max(1) as f32
+    }
+}
+
+impl fmt::Display for Confusion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "correct:   {}", self.correct)?;
+        writeln!(f, "incorrect: {}", self.incorrect)?;
+        write!(f, "accuracy:  {:.2}%", self.accuracy() * 100.0)?;
+        if self.classes == 0 { return Ok(()) }
+        let width = self.counts.iter().max().unwrap_or(&0).to_string().len().max(self.classes.to_string().len()) + 1;
+        //answers across the top, labels down the side
+        write!(f, "\n{:>width$} │", "")?;
+        for c in 0..self.classes { write!(f, "{c:>width$}")? }
+        write!(f, "\n{}┼{}", "─".repeat(width + 1), "─".repeat(width * self.classes))?;
+        for (label, row) in self.counts.chunks_exact(self.classes).enumerate() {
+            write!(f, "\n{label:>width$} │")?;
+            for count in row { write!(f, "{count:>width$}")? }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,299 @@
+use std::fmt;
+
+use rand::{distr::StandardUniform, prelude::*};
+
+use crate::model::*;
+
+//what a ring does with its children's activations
+#[derive(serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum RingKind {
+    //every neuron sees every child activation
+    #[default]
+    Dense,
+    //the children are feature maps, a small kernel slides over them
+    Conv(Conv),
+    //the children are feature maps, shrunk window by window
+    Pool(Pool),
+    //the children, laid together by the ring's merge, passed on as they are.
+    //carries early features forward, or adds a shortcut with sum
+    Identity,
+}
+
+impl RingKind {
+    //false for rings that only move activations around
+    pub fn has_weights(&self) -> bool {
+        !matches!(self, RingKind::Pool(_) | RingKind::Identity)
+    }
+}
+
+//a ring that treats its children as [channels x height x width] maps.
+//the children are stacked channel on channel, so they must share a height and width.
+//a dense ring is a map of neuron_count channels, 1x1.
+//the image ring is a single channel, and square.
+//
+//weight_net holds [channels x (input channels * kernel * kernel)],
+//one row of kernel weights per output channel.
+//bias_net holds one bias per output channel.
+//the output is channel after channel, each height x width, like the input.
+#[derive(serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conv {
+    pub kernel: usize,
+    pub stride: usize,
+    pub padding: usize,
+    pub channels: usize,
+    //the stacked children, (channels, height, width)
+    pub input: (usize, usize, usize),
+}
+
+impl Conv {
+    pub fn output_side(&self) -> (usize, usize) {
+        let (_, height, width) = self.input;
+        let side = |length: usize| (length + 2 * self.padding).saturating_sub(self.kernel) / self.stride.max(1) + 1;
+        (side(height), side(width))
+    }
+    pub fn neuron_count(&self) -> usize {
+        let (height, width) = self.output_side();
+        self.channels * height * width
+    }
+    //the length of one weight row
+    pub fn window(&self) -> usize {
+        self.input.0 * self.kernel * self.kernel
+    }
+
+    //every kernel position becomes a row of the window it covers,
+    //[positions x window]. padding reads as 0.
+    fn columns<F: Scalar>(&self, image: &[F], columns: &mut [F]) {
+        let (in_channels, height, width) = self.input;
+        let (out_height, out_width) = self.output_side();
+        let window = self.window();
+        for oy in 0..out_height {
+            for ox in 0..out_width {
+                let row = &mut columns[(oy*out_width + ox) * window..][..window];
+                let mut i = 0;
+                for c in 0..in_channels {
+                    for ky in 0..self.kernel {
+                        for kx in 0..self.kernel {
+                            let y = (oy * self.stride + ky) as isize - self.padding as isize;
+                            let x = (ox * self.stride + kx) as isize - self.padding as isize;
+                            row[i] = if y < 0 || x < 0 || y >= height as isize || x >= width as isize {
+                                F::zero()
+                            } else {
+                                image[c*height*width + y as usize*width + x as usize]
+                            };
+                            i += 1;
+                        }
+                    }
+                }
+            }
+        }
+    }
+    //the way back for columns, overlapping windows add up
+    fn scatter<F: Scalar>(&self, columns: &[F], image: &mut [F]) {
+        let (in_channels, height, width) = self.input;
+        let (out_height, out_width) = self.output_side();
+        let window = self.window();
+        for oy in 0..out_height {
+            for ox in 0..out_width {
+                let row = &columns[(oy*out_width + ox) * window..][..window];
+                let mut i = 0;
+                for c in 0..in_channels {
+                    for ky in 0..self.kernel {
+                        for kx in 0..self.kernel {
+                            let y = (oy * self.stride + ky) as isize - self.padding as isize;
+                            let x = (ox * self.stride + kx) as isize - self.padding as isize;
+                            if y >= 0 && x >= 0 && y < height as isize && x < width as isize {
+                                image[c*height*width + y as usize*width + x as usize] += row[i];
+                            }
+                            i += 1;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    //gather is [batch x input], output [batch x neuron_count], no biases yet.
+    //each image is one product of its columns against the kernels.
+    pub(crate) fn forward<F: Scalar>(&self, gather: &[F], weights: &[F], output: &mut [F], batch: usize) {
+        let (out_height, out_width) = self.output_side();
+        let positions = out_height * out_width;
+        let (window, inputs, n) = (self.window(), self.input_count(), self.neuron_count());
+        let mut columns = vec![F::zero(); positions * window];
+        let mut product = vec![F::zero(); positions * self.channels];
+        for b in 0..batch {
+            self.columns(&gather[b*inputs..(b+1)*inputs], &mut columns);
+            F::gemm_nt(&columns, weights, &mut product, positions, window, self.channels);
+            //[positions x channels] back to channel after channel
+            let image = &mut output[b*n..(b+1)*n];
+            for p in 0..positions {
+                for c in 0..self.channels {
+                    image[c*positions + p] = product[p*self.channels + c];
+                }
+            }
+        }
+    }
+
+    //delta is [batch x neuron_count]. adds into everything in out,
+    //gather_delta comes in zeroed
+    pub(crate) fn backward<F: Scalar>(&self, gather: &[F], weights: &[F], delta: &[F], out: ConvGradient<F>, batch: usize) {
+        let ConvGradient { mut weight_gradient, mut bias_gradient, gather_delta } = out;
+        let (out_height, out_width) = self.output_side();
+        let positions = out_height * out_width;
+        let (window, inputs, n) = (self.window(), self.input_count(), self.neuron_count());
+        let mut columns = vec![F::zero(); positions * window];
+        let mut column_delta = vec![F::zero(); positions * window];
+        let mut turned = vec![F::zero(); positions * self.channels];
+        for b in 0..batch {
+            let image_delta = &delta[b*n..(b+1)*n];
+            for c in 0..self.channels {
+                for p in 0..positions {
+                    turned[p*self.channels + c] = image_delta[c*positions + p];
+                }
+            }
+            if let Some(bias_gradient) = bias_gradient.as_deref_mut() {
+                for (c, g) in bias_gradient.iter_mut().enumerate() {
+                    *g += image_delta[c*positions..(c+1)*positions].iter().copied().sum::<F>();
+                }
+            }
+            if let Some(weight_gradient) = weight_gradient.as_deref_mut() {
+                self.columns(&gather[b*inputs..(b+1)*inputs], &mut columns);
+                F::gemm_tn(&turned, &columns, weight_gradient, positions, self.channels, window);
+            }
+            column_delta.fill(F::zero());
+            F::gemm_nn(&turned, weights, &mut column_delta, positions, self.channels, window);
+            self.scatter(&column_delta, &mut gather_delta[b*inputs..(b+1)*inputs]);
+        }
+    }
+
+    fn input_count(&self) -> usize {
+        let (channels, height, width) = self.input;
+        channels * height * width
+    }
+}
+
+//where Conv::backward leaves what it worked out.
+//a frozen ring passes None and only hands the error back
+pub(crate) struct ConvGradient<'a, F: Scalar> {
+    pub weight_gradient: Option<&'a mut [F]>,
+    pub bias_gradient: Option<&'a mut [F]>,
+    //the error for the ring's gathered input
+    pub gather_delta: &'a mut [F],
+}
+
+impl fmt::Display for Conv {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (height, width) = self.output_side();
+        write!(f,
+            "conv {k}x{k} stride {} pad {}, {}x{}x{} -> {}x{height}x{width}",
+            self.stride,
+            self.padding,
+            self.input.0,
+            self.input.1,
+            self.input.2,
+            self.channels,
+            k = self.kernel,
+        )
+    }
+}
+
+impl<F: Scalar> Model<F> {
+    //everything the children hand a ring, side by side.
+    //the same as source_activations_count for a dense ring.
+    pub(crate) fn gathered_count(ring_net: &[Vec<Ring>], ring: &Ring) -> usize {
+        let mut widths = ring.children.iter().map(|child| ring_net[child.0][child.1].neuron_count);
+        match ring.merge {
+            Merge::Concat => widths.sum(),
+            //the children are laid on top of each other
+            _ => widths.next().unwrap_or(0),
+        }
+    }
+    //the shape a ring's output has when a conv ring reads it
+    pub(crate) fn feature_map(ring_net: &[Vec<Ring>], child: &Child) -> (usize, usize, usize) {
+        let ring = &ring_net[child.0][child.1];
+        if let RingKind::Conv(conv) = &ring.kind {
+            let (height, width) = conv.output_side();
+            return (conv.channels, height, width)
+        }
+        if let RingKind::Pool(pool) = &ring.kind {
+            let (height, width) = pool.output_side();
+            return (pool.input.0, height, width)
+        }
+        //keeps the maps of its children if they agree on a size
+        if ring.kind == RingKind::Identity && child.0 != 0 {
+            let maps: Vec<(usize, usize, usize)> = ring.children.iter()
+                .map(|child| Self::feature_map(ring_net, child))
+                .collect();
+            let (_, height, width) = maps[0];
+            if maps.iter().all(|map| map.1 == height && map.2 == width) {
+                return (ring.neuron_count / (height * width), height, width)
+            }
+        }
+        if child.0 == 0 {
+            let side = (ring.neuron_count as f64).sqrt() as usize;
+            if side * side == ring.neuron_count { return (1, side, side) }
+        }
+        (ring.neuron_count, 1, 1)
+    }
+
+    pub fn add_conv_ring(
+        &mut self,
+        layer: usize,
+        kernel: usize,
+        stride: usize,
+        padding: usize,
+        channels: usize,
+        children: Vec<Child>,
+        ) {
+        if layer == 0 || layer > self.ring_net.len() || children.is_empty() {
+            println!(">a conv ring needs a layer past 0 and children");
+            return
+        }
+        if kernel == 0 || stride == 0 || channels == 0 {
+            println!(">kernel, stride and channels must be above 0");
+            return
+        }
+        let maps: Vec<(usize, usize, usize)> = children.iter()
+            .map(|child| Self::feature_map(&self.ring_net, child))
+            .collect();
+        let (_, height, width) = maps[0];
+        if maps.iter().any(|map| map.1 != height || map.2 != width) {
+            println!(">children must share a height and width, found {maps:?}");
+            return
+        }
+        if kernel > height + 2 * padding || kernel > width + 2 * padding {
+            println!(">a {kernel}x{kernel} kernel doesn't fit in {height}x{width} with padding {padding}");
+            return
+        }
+        let input_channels = maps.iter().map(|map| map.0).sum();
+        let conv = Conv { kernel, stride, padding, channels, input: (input_channels, height, width) };
+        let neuron_count = conv.neuron_count();
+        let weight_count = channels * conv.window();
+
+        if layer == self.ring_net.len() {
+            self.ring_net.push(Vec::new());
+            self.weight_net.push(Vec::new());
+            self.bias_net.push(Vec::new());
+        }
+        self.quantized = None;
+        let weights = (0..weight_count)
+            .map(|_| F::of_f32((rand::rng().sample::<f32, _>(StandardUniform) - 0.5 ) * 2.0))
+            .collect();
+        let biases = (0..channels)
+            .map(|_| F::of_f32((rand::rng().sample::<f32, _>(StandardUniform) - 0.5 ) * 2.0))
+            .collect();
+        println!(">{conv}");
+        self.ring_net[layer].push(Ring {
+            children,
+            neuron_count,
+            regularize: Regularize::default(),
+            kind: RingKind::Conv(conv),
+            merge: Merge::Concat,
+            trainable: true,
+        });
+        self.weight_net[layer].push(weights);
+        self.bias_net[layer].push(biases);
+        self.settle_norms();
+    }
+}
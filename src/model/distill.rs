@@ -0,0 +1,89 @@
+use std::fmt;
+
+use crate::model::*;
+
+//how a student learns from a teacher's answers as well as the labels
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Distillation {
+    //softens both softmaxes. above 1 the teacher's second
+    //and third guesses start to carry weight
+    pub temperature: f32,
+    //share of the loss that is the teacher's, the rest is the labels'
+    pub soft: f32,
+}
+
+impl Default for Distillation {
+    fn default() -> Distillation {
+        Distillation { temperature: 4.0, soft: 0.5 }
+    }
+}
+
+impl fmt::Display for Distillation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "temperature {}, {:.0}% soft, {:.0}% hard", self.temperature, self.soft * 100.0, (1.0 - self.soft) * 100.0)
+    }
+}
+
+impl Distillation {
+    //error comes in as the hard softmax - one_hot and leaves as the
+    //blend of it and the softened student against the softened teacher.
+    //the soft part is scaled by temperature squared, as its gradient
+    //shrinks by that much, so the mix means the same at any temperature.
+    //returns the blended loss.
+    pub(crate) fn blend<F: Scalar>(&self, logits: &[F], teacher: &[F], error: &mut [F], hard: F) -> F {
+        let temperature = F::of_f32(self.temperature.max(f32::EPSILON));
+        let (soft, hard_share) = (F::of_f32(self.soft), F::of_f32(1.0 - self.soft));
+        let student = softened(logits, temperature);
+        let target = softened(teacher, temperature);
+        let mut divergence = F::zero();
+        for ((e, p), q) in error.iter_mut().zip(&student).zip(&target) {
+            //d(T^2 KL)/dz = T (p - q)
+            *e = hard_share * *e + soft * temperature * (*p - *q);
+            if *q > F::zero() {
+                divergence += *q * (*q / p.max(F::min_positive_value())).ln();
+            }
+        }
+        hard_share * hard + soft * temperature * temperature * divergence
+    }
+}
+
+pub(crate) fn softened<F: Scalar>(logits: &[F], temperature: F) -> Vec<F> {
+    let max = logits.iter().cloned().fold(F::min_value(), F::max);
+    let mut values: Vec<F> = logits.iter().map(|z| ((*z - max) / temperature).exp()).collect();
+    let total = values.iter().cloned().sum::<F>();
+    for value in values.iter_mut() { *value /= total }
+    values
+}
+
+impl<F: Scalar> Model<F> {
+    //trains this model against a teacher's softened answers and the labels.
+    //the teacher only ever runs forward, with its own preprocessing.
+    pub fn distill(&mut self, teacher: &Model<F>, data: &[Vec<f32>], labels: &[u8], config: &TrainConfig) {
+        if teacher.class_count().is_none() {
+            println!(">the teacher is not capped");
+            return
+        }
+        if teacher.input_size() != self.input_size() || teacher.class_count() != self.class_count() {
+            println!(
+                ">the teacher reads {} pixels into {:?} classes, the student {} into {:?}",
+                teacher.input_size(),
+                teacher.class_count(),
+                self.input_size(),
+                self.class_count(),
+            );
+            return
+        }
+        let (_, student) = self.total_parameter_count();
+        let (_, master) = teacher.total_parameter_count();
+        println!(">distilling {master} parameters into {student}, {}", config.distill);
+        self.train_from(data, labels, config, Some(teacher));
+    }
+
+    //the cap's raw outputs for a batch, [batch x classes]
+    pub(crate) fn logits<I: AsRef<[f32]>>(&self, images: &[I], buffers: &mut ActivationBuffers<F>) -> Vec<F> {
+        self.forward_batch(images, buffers);
+        let last_layer = self.ring_net.len() - 1;
+        let classes = self.ring_net[last_layer][0].neuron_count;
+        buffers.activation_net[last_layer][0][..images.len() * classes].to_vec()
+    }
+}
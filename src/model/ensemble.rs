@@ -0,0 +1,188 @@
+use std::io::Error;
+
+use crate::model::*;
+
+//rounds of the weight search, each tries every member up and down
+const TUNE_ROUNDS: usize = 20;
+//what is added to a member's weight on each try. the weights start
+//at 1 and never go under 0, a member taken to 0 can still come back
+const TUNE_STEPS: [f32; 4] = [-0.5, -0.1, 0.1, 0.5];
+
+//how the members' answers become one
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Vote {
+    //each member picks a class, the most picked wins.
+    //a tie goes to the class with the most probability behind it
+    Majority,
+    //the mean of the members' softmaxes
+    Average,
+    //the same with the weights found by tune
+    Weighted,
+}
+
+impl std::str::FromStr for Vote {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Vote, ()> {
+        match s {
+            "vote" | "majority" => Ok(Vote::Majority),
+            "average" => Ok(Vote::Average),
+            "weighted" => Ok(Vote::Weighted),
+            _ => Err(()),
+        }
+    }
+}
+
+//saved models answering together.
+//they must read the same images into the same classes,
+//anything else about their shape can differ.
+pub struct Ensemble<F: Scalar = f32> {
+    members: Vec<(String, Model<F>)>,
+    //one per member, summing to 1
+    weights: Vec<f32>,
+}
+
+impl<F: Scalar> Ensemble<F> {
+    pub fn load(names: &[String]) -> Result<Ensemble<F>, Error> {
+        let mut members: Vec<(String, Model<F>)> = Vec::new();
+        for name in names {
+            let model = Model::<F>::load(name)?;
+            let Some(classes) = model.class_count() else {
+                return Err(Error::other(format!("{name} is not capped")))
+            };
+            let differs = |other: &Model<F>| other.input_size() != model.input_size() || other.class_count() != Some(classes);
+            if let Some((first, _)) = members.first().filter(|(_, other)| differs(other)) {
+                return Err(Error::other(format!("{name} doesn't read or answer like {first}")))
+            }
+            members.push((name.clone(), model));
+        }
+        if members.is_empty() {
+            return Err(Error::other("no members"))
+        }
+        let weights = vec![1.0 / members.len() as f32; members.len()];
+        Ok(Ensemble { members, weights })
+    }
+    pub fn members(&self) -> &[(String, Model<F>)] {
+        &self.members
+    }
+    pub fn class_count(&self) -> usize {
+        self.members[0].1.class_count().unwrap_or(0)
+    }
+
+    //every member's softmax for every image, [member][image x classes]
+    fn probabilities<I: AsRef<[f32]>>(&self, data: &[I]) -> Vec<Vec<f32>> {
+        self.members.iter().map(|(_, model)| {
+            let mut buffers = ActivationBuffers::new(model, TEST_BATCH);
+            let mut probabilities = Vec::with_capacity(data.len() * self.class_count());
+            for images in data.chunks(TEST_BATCH) {
+                let logits = model.logits(images, &mut buffers);
+                for row in logits.chunks_exact(self.class_count()) {
+                    probabilities.extend(softened(row, F::one()).iter().map(|p| p.as_f32()));
+                }
+            }
+            probabilities
+        }).collect()
+    }
+    //one image's answer out of the members' probabilities for it
+    fn combine(&self, rows: &[&[f32]], vote: Vote) -> usize {
+        let classes = self.class_count();
+        let mut mixed = vec![0f32; classes];
+        let uniform = 1.0 / rows.len() as f32;
+        for (m, row) in rows.iter().enumerate() {
+            let weight = if vote == Vote::Weighted { self.weights[m] } else { uniform };
+            for (value, p) in mixed.iter_mut().zip(*row) { *value += weight * p }
+        }
+        if vote != Vote::Majority { return argmax(&mixed) }
+        let mut votes = vec![0usize; classes];
+        for row in rows { votes[argmax(row)] += 1 }
+        let most = *votes.iter().max().unwrap_or(&0);
+        (0..classes)
+            .filter(|c| votes[*c] == most)
+            .max_by(|a, b| mixed[*a].total_cmp(&mixed[*b]))
+            .unwrap_or(usize::MAX)
+    }
+
+    pub fn predict(&self, image: &Vec<f32>, vote: Vote) -> usize {
+        let probabilities = self.probabilities(std::slice::from_ref(image));
+        let rows: Vec<&[f32]> = probabilities.iter().map(|p| p.as_slice()).collect();
+        self.combine(&rows, vote)
+    }
+
+    //each member's confusion, then the ensemble's
+    pub fn test(&self, data: &[Vec<f32>], labels: &[u8], vote: Vote) {
+        let classes = self.class_count();
+        let probabilities = self.probabilities(data);
+        for ((name, _), member) in self.members.iter().zip(&probabilities) {
+            let mut confusion = Confusion::new(classes);
+            for (row, answer) in member.chunks_exact(classes).zip(labels) {
+                confusion.add(*answer, argmax(row));
+            }
+            println!(">{name}");
+            println!("{confusion}");
+        }
+        let mut confusion = Confusion::new(classes);
+        for (i, answer) in labels.iter().enumerate() {
+            let rows: Vec<&[f32]> = probabilities.iter().map(|p| &p[i*classes..(i+1)*classes]).collect();
+            confusion.add(*answer, self.combine(&rows, vote));
+        }
+        println!(">ensemble of {}, {vote:?}", self.members.len());
+        if vote == Vote::Weighted {
+            for ((name, _), weight) in self.members.iter().zip(&self.weights) {
+                println!(">  {name}: {weight:.3}");
+            }
+        }
+        println!("{confusion}");
+    }
+
+    //the images every member held back, the only ones none of them
+    //learned from. members trained under different splits have none.
+    pub fn held_back(&self, labels: &[u8]) -> Result<Vec<usize>, Error> {
+        let (first, model) = &self.members[0];
+        let split = model.split();
+        if let Some((name, _)) = self.members.iter().find(|(_, model)| model.split() != split) {
+            return Err(Error::other(format!("{name} held back other images than {first}")))
+        }
+        let held_back = split.held_back(labels);
+        if held_back.is_empty() {
+            return Err(Error::other(format!("the members held nothing back, {split}")))
+        }
+        Ok(held_back)
+    }
+
+    //coordinate search for the weights that give the held back images
+    //the lowest cross entropy. the members' answers are worked out once.
+    //the images should be ones no member trained on, see held_back.
+    pub fn tune<I: AsRef<[f32]>>(&mut self, data: &[I], labels: &[u8]) {
+        let classes = self.class_count();
+        let probabilities = self.probabilities(data);
+        let loss = |weights: &[f32]| -> f32 {
+            let total: f32 = weights.iter().sum();
+            if total <= 0.0 { return f32::INFINITY }
+            labels.iter().enumerate().map(|(i, answer)| {
+                let p: f32 = probabilities.iter().zip(weights)
+                    .map(|(member, weight)| weight * member[i*classes + *answer as usize])
+                    .sum();
+                -(p / total).max(f32::MIN_POSITIVE).ln()
+            }).sum::<f32>() / labels.len().max(1) as f32
+        };
+        let mut weights = vec![1.0; self.members.len()];
+        let mut best = loss(&weights);
+        println!(">uniform weights, validation loss {best:.4}");
+        for _ in 0..TUNE_ROUNDS {
+            let mut moved = false;
+            for m in 0..weights.len() {
+                for step in TUNE_STEPS {
+                    let mut tried = weights.clone();
+                    tried[m] = (tried[m] + step).max(0.0);
+                    let tried_loss = loss(&tried);
+                    if tried_loss < best {
+                        (best, weights, moved) = (tried_loss, tried, true);
+                    }
+                }
+            }
+            if !moved { break }
+        }
+        let total: f32 = weights.iter().sum();
+        self.weights = weights.iter().map(|w| w / total).collect();
+        println!(">tuned weights, validation loss {best:.4}");
+    }
+}
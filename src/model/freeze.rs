@@ -0,0 +1,35 @@
+use crate::model::*;
+
+//rings load as trainable from files that never heard of freezing
+pub(crate) fn trainable_default() -> bool {
+    true
+}
+
+impl<F: Scalar> Model<F> {
+    //a frozen ring still passes its error back to its children,
+    //it only stops moving itself
+    pub fn set_trainable(&mut self, layer: usize, ring: usize, trainable: bool) {
+        if layer == 0 || self.ring_net.get(layer).and_then(|rings| rings.get(ring)).is_none() {
+            println!(">no ring at [{layer}][{ring}]");
+            return
+        }
+        self.ring_net[layer][ring].trainable = trainable;
+    }
+    pub fn set_layer_trainable(&mut self, layer: usize, trainable: bool) {
+        if layer == 0 || layer >= self.ring_net.len() {
+            println!(">no layer {layer}");
+            return
+        }
+        for ring in self.ring_net[layer].iter_mut() {
+            ring.trainable = trainable;
+        }
+    }
+    pub fn unfreeze_all(&mut self) {
+        for ring in self.ring_net.iter_mut().flatten() {
+            ring.trainable = true;
+        }
+    }
+    pub fn frozen_count(&self) -> usize {
+        self.ring_net.iter().skip(1).flatten().filter(|ring| !ring.trainable).count()
+    }
+}
@@ -0,0 +1,220 @@
+use rand::{distr::StandardUniform, prelude::*};
+
+use crate::model::*;
+
+//central differences are only trustworthy in f64,
+//so every check runs on an f64 copy of the model.
+const STEP: f64 = 1e-5;
+pub const GRADCHECK_TOLERANCE: f64 = 1e-4;
+//checking every weight of a wide ring takes forever.
+//a random handful per ring finds a broken gradient just as well.
+const SAMPLES_PER_RING: usize = 40;
+//two losses STEP apart can't show a slope finer than the rounding
+//in them, about epsilon * loss / STEP. a pair of gradients so small
+//that this many times the rounding is past the tolerance is noise
+//on either side, and skipped.
+const NOISE_MARGIN: f64 = 10.0;
+
+pub struct RingCheck {
+    pub layer: usize,
+    pub ring: usize,
+    pub checked: usize,
+    pub max_relative_error: f64,
+    //a ring with something to check where every pick was noise
+    //proved nothing, and says so instead of passing
+    pub unchecked: bool,
+    pub flagged: bool,
+}
+
+//compares reflection's gradients against nudging each sampled
+//weight and bias both ways and watching the loss move.
+//this is the helper the command uses, usable on any model.
+pub fn gradcheck<F: Scalar>(
+    model: &Model<F>,
+    images: &[Vec<f32>],
+    labels: &[u8],
+    tolerance: f64,
+    seed: u64,
+    ) -> Vec<RingCheck> {
+    if let ModelState::Malformed = model.state {
+        println!(">model is not capped");
+        return Vec::new()
+    }
+    let mut model = model.convert::<f64>();
+    //int8 rings would hide the very weights being nudged
+    model.quantized = None;
+    //and frozen ones skip their weight gradients
+    model.unfreeze_all();
+    //and sparse ones only the weights they kept
+    model.sparse.clear();
+    let mut buffers = ActivationBuffers::new(&model, images.len());
+    let mut analytic = Gradients::new(&model);
+    let loss = model.reflection(images, labels, &mut buffers, &mut analytic);
+    let floor = NOISE_MARGIN * f64::EPSILON * loss.abs().max(1.0) / STEP / tolerance;
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut report = Vec::new();
+    for l in 1..model.ring_net.len() {
+        for r in 0..model.ring_net[l].len() {
+            let mut worst = 0f64;
+            let mut checked = 0;
+
+            let weight_count = model.weight_net[l][r].len();
+            let bias_count = model.bias_net[l][r].len();
+            let mut picks: Vec<(bool, usize)> = (0..weight_count).map(|i| (true, i))
+                .chain((0..bias_count).map(|i| (false, i)))
+                .collect();
+            picks.shuffle(&mut rng);
+            picks.truncate(SAMPLES_PER_RING);
+            let unchecked = !picks.is_empty();
+
+            for (is_weight, i) in picks {
+                let expected = if is_weight {
+                    analytic.weight_net[l][r][i]
+                } else {
+                    analytic.bias_net[l][r][i]
+                };
+                let numeric = {
+                    let nudge = |model: &mut Model<f64>, by: f64| {
+                        if is_weight { model.weight_net[l][r][i] += by } else { model.bias_net[l][r][i] += by }
+                    };
+                    nudge(&mut model, STEP);
+                    let up = model.loss(images, labels, &mut buffers);
+                    nudge(&mut model, -2.0 * STEP);
+                    let down = model.loss(images, labels, &mut buffers);
+                    nudge(&mut model, STEP);
+                    (up - down) / (2.0 * STEP)
+                };
+                let scale = expected.abs().max(numeric.abs());
+                if scale < floor { continue }
+                worst = worst.max((expected - numeric).abs() / scale);
+                checked += 1;
+            }
+            let unchecked = unchecked && checked == 0;
+            report.push(RingCheck {
+                layer: l,
+                ring: r,
+                checked,
+                max_relative_error: worst,
+                unchecked,
+                flagged: unchecked || worst > tolerance,
+            });
+        }
+    }
+    report
+}
+
+pub fn print_gradcheck(report: &[RingCheck], tolerance: f64) {
+    println!(">max relative error per ring, tolerance {tolerance:e}");
+    for check in report {
+        let flag = if check.unchecked {
+            "  <-- every pick under the noise floor, unchecked"
+        } else if check.flagged {
+            "  <-- over tolerance"
+        } else {
+            ""
+        };
+        println!(
+            "[{}][{}] {:>3} checked  {:e}{flag}",
+            check.layer,
+            check.ring,
+            check.checked,
+            check.max_relative_error,
+        );
+    }
+    let flagged = report.iter().filter(|check| check.flagged).count();
+    println!(">{flagged} of {} rings flagged", report.len());
+}
+
+//a small net with the awkward topologies in it:
+//a skip connection from the image past layer 1,
+//and layer 1 feeding two parents.
+pub fn gradcheck_model(seed: u64) -> Model<f64> {
+    let mut model = Model::<f64>::new(2, 6, 28*28);
+    model.add_ring(2, 4, vec![Child(0,0), Child(1,0)]);
+    model.cap(10);
+    reseed(&mut model, seed);
+    model
+}
+
+//new rings draw from an unseeded rng, so the weights are
+//drawn again from the seed to make a failure come back.
+//kept near 1/sqrt(fan in), or the softmax saturates and the
+//loss grows until every gradient is under the noise floor.
+pub fn reseed(model: &mut Model<f64>, seed: u64) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    for (weights, biases) in model.weight_net.iter_mut().flatten().zip(model.bias_net.iter_mut().flatten()) {
+        if biases.is_empty() { continue }
+        let bound = 1.0 / ((weights.len() / biases.len()).max(1) as f64).sqrt();
+        for value in weights.iter_mut().chain(biases.iter_mut()) {
+            *value = rng.random_range(-bound..bound);
+        }
+    }
+}
+
+//random images and labels for the small net
+pub fn gradcheck_batch(count: usize, pixels: usize, classes: u8, seed: u64) -> (Vec<Vec<f32>>, Vec<u8>) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let images = (0..count)
+        .map(|_| (0..pixels).map(|_| rng.sample::<f32, _>(StandardUniform)).collect())
+        .collect();
+    let labels = (0..count).map(|_| rng.random_range(0..classes)).collect();
+    (images, labels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEED: u64 = 7;
+
+    fn assert_passes(model: &Model<f64>, pixels: usize, classes: u8) {
+        let (images, labels) = gradcheck_batch(8, pixels, classes, SEED);
+        let report = gradcheck(model, &images, &labels, GRADCHECK_TOLERANCE, SEED);
+        assert_eq!(report.len(), model.ring_net.iter().skip(1).map(|layer| layer.len()).sum::<usize>());
+        for check in &report {
+            assert!(!check.unchecked, "[{}][{}] unchecked", check.layer, check.ring);
+            assert!(!check.flagged, "[{}][{}] off by {:e}", check.layer, check.ring, check.max_relative_error);
+        }
+    }
+
+    #[test]
+    fn dense_gradients_match() {
+        assert_passes(&gradcheck_model(SEED), 28*28, 10);
+    }
+
+    #[test]
+    fn all_noise_ring_is_unchecked() {
+        //every weight at 0 leaves nothing above the noise floor
+        let mut model = gradcheck_model(SEED);
+        for value in model.weight_net.iter_mut().flatten().flatten() { *value = 0.0 }
+        for value in model.bias_net.iter_mut().flatten().flatten() { *value = 0.0 }
+        let (images, labels) = gradcheck_batch(8, 28*28, 10, SEED);
+        let report = gradcheck(&model, &images, &labels, GRADCHECK_TOLERANCE, SEED);
+        let check = &report[0];
+        assert_eq!(check.checked, 0);
+        assert!(check.unchecked && check.flagged);
+    }
+
+    #[test]
+    fn conv_pool_norm_and_sum_gradients_match() {
+        //a 6x6 image read by a dense ring and a conv ring,
+        //the conv pooled, and a dense ring summed with its own child
+        let mut model = Model::<f64>::new(1, 8, 36);
+        model.add_conv_ring(1, 3, 1, 1, 2, vec![Child(0,0)]);
+        model.add_pool_ring(2, PoolMode::Max, 2, 2, vec![Child(1,1)]);
+        model.add_ring(2, 8, vec![Child(1,0)]);
+        model.add_ring(3, 8, vec![Child(1,0), Child(2,1)]);
+        model.set_merge(3, 0, Merge::Sum);
+        model.add_ring(3, 4, vec![Child(2,0)]);
+        model.set_norm(1, 0, NormKind::Layer);
+        model.set_norm(1, 1, NormKind::Batch);
+        model.set_norm(3, 0, NormKind::Layer);
+        model.cap(3);
+        reseed(&mut model, SEED);
+        assert!(matches!(model.ring_net[1][1].kind, RingKind::Conv(_)));
+        assert!(matches!(model.ring_net[2][0].kind, RingKind::Pool(_)));
+        assert_eq!(model.merge(3, 0), Some(Merge::Sum));
+        assert_passes(&model, 36, 3);
+    }
+}
@@ -0,0 +1,140 @@
+use crate::model::*;
+
+//images the gradients are measured on when choosing where to grow
+const GROWTH_SAMPLE: usize = 256;
+
+//train, add a ring where the error pushes hardest, train again
+#[derive(Debug, Clone)]
+pub struct Growth {
+    pub rounds: usize,
+    pub epochs: usize,
+    pub neurons: usize,
+    pub from: GrowFrom,
+}
+
+//what each new ring reads
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum GrowFrom {
+    //a copy of the busiest ring's children, so it starts beside it
+    #[default]
+    Copy,
+    //every ring of the layer below, whatever the busiest ring reads
+    Below,
+}
+
+impl std::str::FromStr for GrowFrom {
+    type Err = ();
+    fn from_str(s: &str) -> Result<GrowFrom, ()> {
+        match s {
+            "copy" => Ok(GrowFrom::Copy),
+            "below" => Ok(GrowFrom::Below),
+            _ => Err(()),
+        }
+    }
+}
+
+impl<F: Scalar> Model<F> {
+    //the new ring reads what growth.from says, and joins
+    //every parent the busiest ring in the chosen layer feeds.
+    //its outgoing weights start at 0, so growing alone doesn't
+    //change a single answer, only the training after it does.
+    pub fn grow(
+        &mut self,
+        data: &[Vec<f32>],
+        labels: &[u8],
+        test_data: &[Vec<f32>],
+        test_labels: &[u8],
+        config: &TrainConfig,
+        growth: &Growth,
+        ) {
+        if let ModelState::Malformed = self.state {
+            println!(">model is not capped");
+            return
+        }
+        let round_config = TrainConfig { epochs: growth.epochs, ..config.clone() };
+        println!(">growth: {} rounds of {} epochs, {} neurons per ring", growth.rounds, growth.epochs, growth.neurons);
+        self.train(data, labels, &round_config);
+        let mut accuracy = self.accuracy(test_data, test_labels);
+        println!(">starting accuracy {:.2}%", accuracy * 100.0);
+
+        for round in 0..growth.rounds {
+            let sample = data.len().min(GROWTH_SAMPLE);
+            let Some((layer, reference)) = self.busiest_ring(&data[..sample], &labels[..sample]) else {
+                println!(">no dense hidden ring to grow beside");
+                return
+            };
+            let children = match growth.from {
+                GrowFrom::Copy => self.ring_net[layer][reference].children.clone(),
+                GrowFrom::Below => (0..self.ring_net[layer - 1].len()).map(|r| Child(layer - 1, r)).collect(),
+            };
+            self.add_ring(layer, growth.neurons, children.clone());
+            let ring = self.ring_net[layer].len() - 1;
+
+            let mut parents = Vec::new();
+            for l in layer + 1..self.ring_net.len() {
+                for r in 0..self.ring_net[l].len() {
+                    let parent = &self.ring_net[l][r];
+                    let feeds = parent.children.iter().any(|child| child.0 == layer && child.1 == reference);
+                    if feeds && parent.kind == RingKind::Dense && parent.merge == Merge::Concat {
+                        parents.push((l, r));
+                    }
+                }
+            }
+            for (l, r) in &parents {
+                let old_count = Self::source_activations_count(&self.ring_net, &self.ring_net[*l][*r]);
+                self.add_children((*l, *r), &vec![Child(layer, ring)]);
+                let new_count = Self::source_activations_count(&self.ring_net, &self.ring_net[*l][*r]);
+                for row in self.weight_net[*l][*r].chunks_exact_mut(new_count) {
+                    row[old_count..].fill(F::zero());
+                }
+            }
+            println!(
+                ">round {}: added [{layer}][{ring}], {} neurons from {:?}, feeding {:?}",
+                round + 1,
+                growth.neurons,
+                children,
+                parents,
+            );
+
+            self.train(data, labels, &round_config);
+            let after = self.accuracy(test_data, test_labels);
+            println!(">round {}: accuracy {:.2}% -> {:.2}%", round + 1, accuracy * 100.0, after * 100.0);
+            accuracy = after;
+        }
+    }
+
+    //the hidden layer whose weights and biases the error pulls
+    //on hardest, per parameter, and its busiest dense ring
+    fn busiest_ring(&self, images: &[Vec<f32>], labels: &[u8]) -> Option<(usize, usize)> {
+        let mut buffers = ActivationBuffers::new(self, images.len());
+        let mut gradients = Gradients::new(self);
+        self.reflection(images, labels, &mut buffers, &mut gradients);
+        let pull = |l: usize, r: usize| {
+            let values = gradients.weight_net[l][r].iter().chain(&gradients.bias_net[l][r]);
+            values.fold((0f32, 0usize), |(sum, count), g| (sum + g.as_f32().abs(), count + 1))
+        };
+        let last_layer = self.ring_net.len() - 1;
+        let mut busiest: Option<(f32, usize, usize)> = None;
+        for l in 1..last_layer {
+            let (mut sum, mut count) = (0f32, 0usize);
+            let mut best_ring: Option<(f32, usize)> = None;
+            for (r, ring) in self.ring_net[l].iter().enumerate() {
+                let (ring_sum, ring_count) = pull(l, r);
+                sum += ring_sum;
+                count += ring_count;
+                if ring.kind != RingKind::Dense || ring_count == 0 { continue }
+                let mean = ring_sum / ring_count as f32;
+                if best_ring.is_none_or(|(best, _)| mean > best) {
+                    best_ring = Some((mean, r));
+                }
+            }
+            let Some((_, r)) = best_ring else { continue };
+            let mean = sum / count.max(1) as f32;
+            println!(">layer {l}: mean gradient {mean:e}");
+            if busiest.is_none_or(|(best, _, _)| mean > best) {
+                busiest = Some((mean, l, r));
+            }
+        }
+        busiest.map(|(_, l, r)| (l, r))
+    }
+}
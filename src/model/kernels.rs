@@ -0,0 +1,441 @@
+use std::sync::OnceLock;
+
+use rand::{distr::StandardUniform, prelude::*};
+
+//the three products a ring ever needs.
+//weights are [n x k], one neuron row after another.
+//
+//  forward:          out[m x n]  = input[m x k] * weights[n x k]^T    gemm_nt
+//  backward input:   out[m x k] += delta[m x n] * weights[n x k]      gemm_nn
+//  backward weights: out[n x k] += delta[m x n]^T * input[m x k]      gemm_tn
+//
+//all three only ever walk contiguous rows, so they break down
+//into a dot product or a scaled add (axpy) over a row of k.
+//those two are the only places the instruction set matters.
+
+//how many weight rows stay hot in cache while the batch walks past them
+const ROW_BLOCK: usize = 64;
+//how many batch rows walk past a block of weights at a time
+const BATCH_BLOCK: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kernel {
+    Scalar,
+    Avx2,
+    //only ever detected on aarch64
+    #[cfg_attr(not(target_arch = "aarch64"), allow(dead_code))]
+    Neon,
+}
+
+static DETECTED: OnceLock<Kernel> = OnceLock::new();
+
+//checked once, the first time a ring needs a product
+pub fn detected() -> Kernel {
+    *DETECTED.get_or_init(|| *available().last().unwrap())
+}
+
+//every kernel this cpu can run, scalar always first.
+//a Kernel should only ever come from here, the simd
+//arms trust that the feature was detected.
+pub fn available() -> Vec<Kernel> {
+    #[allow(unused_mut)]
+    let mut kernels = vec![Kernel::Scalar];
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx2") && is_x86_feature_detected!("fma") {
+            kernels.push(Kernel::Avx2);
+        }
+    }
+    #[cfg(target_arch = "aarch64")]
+    {
+        if std::arch::is_aarch64_feature_detected!("neon") {
+            kernels.push(Kernel::Neon);
+        }
+    }
+    kernels
+}
+
+impl Kernel {
+    fn dot(self, a: &[f32], b: &[f32]) -> f32 {
+        match self {
+            #[cfg(target_arch = "x86_64")]
+            Kernel::Avx2 => unsafe { avx2::dot(a, b) },
+            #[cfg(target_arch = "aarch64")]
+            Kernel::Neon => unsafe { neon::dot(a, b) },
+            _ => scalar::dot(a, b),
+        }
+    }
+    fn axpy(self, alpha: f32, x: &[f32], y: &mut [f32]) {
+        match self {
+            #[cfg(target_arch = "x86_64")]
+            Kernel::Avx2 => unsafe { avx2::axpy(alpha, x, y) },
+            #[cfg(target_arch = "aarch64")]
+            Kernel::Neon => unsafe { neon::axpy(alpha, x, y) },
+            _ => scalar::axpy(alpha, x, y),
+        }
+    }
+
+    pub fn gemm_nt(self, input: &[f32], weights: &[f32], out: &mut [f32], m: usize, k: usize, n: usize) {
+        for j0 in (0..n).step_by(ROW_BLOCK) {
+            let j1 = (j0 + ROW_BLOCK).min(n);
+            for i0 in (0..m).step_by(BATCH_BLOCK) {
+                let i1 = (i0 + BATCH_BLOCK).min(m);
+                for i in i0..i1 {
+                    let row = &input[i*k..(i+1)*k];
+                    for j in j0..j1 {
+                        out[i*n + j] = self.dot(row, &weights[j*k..(j+1)*k]);
+                    }
+                }
+            }
+        }
+    }
+    pub fn gemm_nn(self, delta: &[f32], weights: &[f32], out: &mut [f32], m: usize, n: usize, k: usize) {
+        for j0 in (0..n).step_by(ROW_BLOCK) {
+            let j1 = (j0 + ROW_BLOCK).min(n);
+            for i in 0..m {
+                let out_row = &mut out[i*k..(i+1)*k];
+                for j in j0..j1 {
+                    let alpha = delta[i*n + j];
+                    //Relu leaves plenty of these behind
+                    if alpha == 0.0 { continue }
+                    self.axpy(alpha, &weights[j*k..(j+1)*k], out_row);
+                }
+            }
+        }
+    }
+    pub fn gemm_tn(self, delta: &[f32], input: &[f32], out: &mut [f32], m: usize, n: usize, k: usize) {
+        for j0 in (0..n).step_by(ROW_BLOCK) {
+            let j1 = (j0 + ROW_BLOCK).min(n);
+            for i0 in (0..m).step_by(BATCH_BLOCK) {
+                let i1 = (i0 + BATCH_BLOCK).min(m);
+                for i in i0..i1 {
+                    let row = &input[i*k..(i+1)*k];
+                    for j in j0..j1 {
+                        let alpha = delta[i*n + j];
+                        if alpha == 0.0 { continue }
+                        self.axpy(alpha, row, &mut out[j*k..(j+1)*k]);
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub fn gemm_nt(input: &[f32], weights: &[f32], out: &mut [f32], m: usize, k: usize, n: usize) {
+    detected().gemm_nt(input, weights, out, m, k, n)
+}
+pub fn gemm_nn(delta: &[f32], weights: &[f32], out: &mut [f32], m: usize, n: usize, k: usize) {
+    detected().gemm_nn(delta, weights, out, m, n, k)
+}
+pub fn gemm_tn(delta: &[f32], input: &[f32], out: &mut [f32], m: usize, n: usize, k: usize) {
+    detected().gemm_tn(delta, input, out, m, n, k)
+}
+
+//the same blocking for any float, without the hand written lanes.
+//this is the f64 path, where correctness matters more than speed.
+pub mod generic {
+    use std::ops::AddAssign;
+    use num_traits::Float;
+    use super::{BATCH_BLOCK, ROW_BLOCK};
+
+    pub fn gemm_nt<T: Float>(input: &[T], weights: &[T], out: &mut [T], m: usize, k: usize, n: usize) {
+        for j0 in (0..n).step_by(ROW_BLOCK) {
+            let j1 = (j0 + ROW_BLOCK).min(n);
+            for i in 0..m {
+                let row = &input[i*k..(i+1)*k];
+                for j in j0..j1 {
+                    let neuron = &weights[j*k..(j+1)*k];
+                    out[i*n + j] = row.iter().zip(neuron).fold(T::zero(), |sum, (a, w)| sum + *a * *w);
+                }
+            }
+        }
+    }
+    pub fn gemm_nn<T: Float + AddAssign>(delta: &[T], weights: &[T], out: &mut [T], m: usize, n: usize, k: usize) {
+        for j0 in (0..n).step_by(ROW_BLOCK) {
+            let j1 = (j0 + ROW_BLOCK).min(n);
+            for i in 0..m {
+                let out_row = &mut out[i*k..(i+1)*k];
+                for j in j0..j1 {
+                    let alpha = delta[i*n + j];
+                    if alpha == T::zero() { continue }
+                    for (o, w) in out_row.iter_mut().zip(&weights[j*k..(j+1)*k]) {
+                        *o += alpha * *w;
+                    }
+                }
+            }
+        }
+    }
+    pub fn gemm_tn<T: Float + AddAssign>(delta: &[T], input: &[T], out: &mut [T], m: usize, n: usize, k: usize) {
+        for j0 in (0..n).step_by(ROW_BLOCK) {
+            let j1 = (j0 + ROW_BLOCK).min(n);
+            for i0 in (0..m).step_by(BATCH_BLOCK) {
+                let i1 = (i0 + BATCH_BLOCK).min(m);
+                for i in i0..i1 {
+                    let row = &input[i*k..(i+1)*k];
+                    for j in j0..j1 {
+                        let alpha = delta[i*n + j];
+                        if alpha == T::zero() { continue }
+                        for (o, a) in out[j*k..(j+1)*k].iter_mut().zip(row) {
+                            *o += alpha * *a;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+//written in lanes of 8 so the compiler can see the vector shape
+//on targets where we have no explicit path.
+mod scalar {
+    pub fn dot(a: &[f32], b: &[f32]) -> f32 {
+        let len = a.len().min(b.len());
+        let (a, b) = (&a[..len], &b[..len]);
+        let mut lanes = [0f32; 8];
+        let mut a_chunks = a.chunks_exact(8);
+        let mut b_chunks = b.chunks_exact(8);
+        for (x, y) in (&mut a_chunks).zip(&mut b_chunks) {
+            for i in 0..8 {
+                lanes[i] += x[i] * y[i];
+            }
+        }
+        let mut sum: f32 = lanes.iter().sum();
+        for (x, y) in a_chunks.remainder().iter().zip(b_chunks.remainder()) {
+            sum += x * y;
+        }
+        sum
+    }
+    pub fn axpy(alpha: f32, x: &[f32], y: &mut [f32]) {
+        for (y, x) in y.iter_mut().zip(x) {
+            *y += alpha * x;
+        }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod avx2 {
+    use std::arch::x86_64::*;
+
+    #[target_feature(enable = "avx2,fma")]
+    pub unsafe fn dot(a: &[f32], b: &[f32]) -> f32 {
+        let len = a.len().min(b.len());
+        let (pa, pb) = (a.as_ptr(), b.as_ptr());
+        let mut i = 0;
+        unsafe {
+            let mut acc0 = _mm256_setzero_ps();
+            let mut acc1 = _mm256_setzero_ps();
+            while i + 16 <= len {
+                acc0 = _mm256_fmadd_ps(_mm256_loadu_ps(pa.add(i)), _mm256_loadu_ps(pb.add(i)), acc0);
+                acc1 = _mm256_fmadd_ps(_mm256_loadu_ps(pa.add(i + 8)), _mm256_loadu_ps(pb.add(i + 8)), acc1);
+                i += 16;
+            }
+            if i + 8 <= len {
+                acc0 = _mm256_fmadd_ps(_mm256_loadu_ps(pa.add(i)), _mm256_loadu_ps(pb.add(i)), acc0);
+                i += 8;
+            }
+            let mut lanes = [0f32; 8];
+            _mm256_storeu_ps(lanes.as_mut_ptr(), _mm256_add_ps(acc0, acc1));
+            let mut sum: f32 = lanes.iter().sum();
+            while i < len {
+                sum += a[i] * b[i];
+                i += 1;
+            }
+            sum
+        }
+    }
+    #[target_feature(enable = "avx2,fma")]
+    pub unsafe fn axpy(alpha: f32, x: &[f32], y: &mut [f32]) {
+        let len = x.len().min(y.len());
+        let (px, py) = (x.as_ptr(), y.as_mut_ptr());
+        let mut i = 0;
+        unsafe {
+            let va = _mm256_set1_ps(alpha);
+            while i + 8 <= len {
+                let vy = _mm256_loadu_ps(py.add(i));
+                let vx = _mm256_loadu_ps(px.add(i));
+                _mm256_storeu_ps(py.add(i), _mm256_fmadd_ps(va, vx, vy));
+                i += 8;
+            }
+        }
+        while i < len {
+            y[i] += alpha * x[i];
+            i += 1;
+        }
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod neon {
+    use std::arch::aarch64::*;
+
+    #[target_feature(enable = "neon")]
+    pub unsafe fn dot(a: &[f32], b: &[f32]) -> f32 {
+        let len = a.len().min(b.len());
+        let (pa, pb) = (a.as_ptr(), b.as_ptr());
+        let mut i = 0;
+        unsafe {
+            let mut acc0 = vdupq_n_f32(0.0);
+            let mut acc1 = vdupq_n_f32(0.0);
+            while i + 8 <= len {
+                acc0 = vfmaq_f32(acc0, vld1q_f32(pa.add(i)), vld1q_f32(pb.add(i)));
+                acc1 = vfmaq_f32(acc1, vld1q_f32(pa.add(i + 4)), vld1q_f32(pb.add(i + 4)));
+                i += 8;
+            }
+            if i + 4 <= len {
+                acc0 = vfmaq_f32(acc0, vld1q_f32(pa.add(i)), vld1q_f32(pb.add(i)));
+                i += 4;
+            }
+            let mut sum = vaddvq_f32(vaddq_f32(acc0, acc1));
+            while i < len {
+                sum += a[i] * b[i];
+                i += 1;
+            }
+            sum
+        }
+    }
+    #[target_feature(enable = "neon")]
+    pub unsafe fn axpy(alpha: f32, x: &[f32], y: &mut [f32]) {
+        let len = x.len().min(y.len());
+        let (px, py) = (x.as_ptr(), y.as_mut_ptr());
+        let mut i = 0;
+        unsafe {
+            let va = vdupq_n_f32(alpha);
+            while i + 4 <= len {
+                let vy = vld1q_f32(py.add(i));
+                let vx = vld1q_f32(px.add(i));
+                vst1q_f32(py.add(i), vfmaq_f32(vy, vx, va));
+                i += 4;
+            }
+        }
+        while i < len {
+            y[i] += alpha * x[i];
+            i += 1;
+        }
+    }
+}
+
+//the obvious triple loops. slow, but nothing to get wrong.
+mod reference {
+    pub fn gemm_nt(input: &[f32], weights: &[f32], out: &mut [f32], m: usize, k: usize, n: usize) {
+        for i in 0..m {
+            for j in 0..n {
+                let mut sum = 0.0;
+                for p in 0..k {
+                    sum += input[i*k + p] * weights[j*k + p];
+                }
+                out[i*n + j] = sum;
+            }
+        }
+    }
+    pub fn gemm_nn(delta: &[f32], weights: &[f32], out: &mut [f32], m: usize, n: usize, k: usize) {
+        for i in 0..m {
+            for p in 0..k {
+                let mut sum = 0.0;
+                for j in 0..n {
+                    sum += delta[i*n + j] * weights[j*k + p];
+                }
+                out[i*k + p] += sum;
+            }
+        }
+    }
+    pub fn gemm_tn(delta: &[f32], input: &[f32], out: &mut [f32], m: usize, n: usize, k: usize) {
+        for j in 0..n {
+            for p in 0..k {
+                let mut sum = 0.0;
+                for i in 0..m {
+                    sum += delta[i*n + j] * input[i*k + p];
+                }
+                out[j*k + p] += sum;
+            }
+        }
+    }
+}
+
+//odd sizes on purpose, so every remainder path and block edge gets walked.
+//every shape has a k or n off the 8 wide avx2 and 4 wide neon lanes
+const CHECK_SHAPES: [(usize, usize, usize); 8] = [
+    (1, 1, 1),
+    (2, 3, 2),
+    (3, 7, 5),
+    (5, 13, 3),
+    (17, 33, 9),
+    (33, 100, 65),
+    (64, 784, 10),
+    (130, 19, 129),
+];
+const CHECK_TOLERANCE: f32 = 1e-4;
+
+//the worst relative error of gemm_nt, gemm_nn and gemm_tn
+//against the reference loops, over every check shape
+fn worst_errors(kernel: Kernel) -> [f32; 3] {
+    let mut worst = [0f32; 3];
+    for (m, k, n) in CHECK_SHAPES {
+        let input = random_matrix(m * k);
+        let weights = random_matrix(n * k);
+        let delta = random_matrix(m * n);
+
+        let mut expected = vec![0f32; m * n];
+        let mut actual = vec![0f32; m * n];
+        reference::gemm_nt(&input, &weights, &mut expected, m, k, n);
+        kernel.gemm_nt(&input, &weights, &mut actual, m, k, n);
+        worst[0] = worst[0].max(max_relative_error(&expected, &actual));
+
+        //the backward products accumulate, so start them off non-zero
+        let start = random_matrix(m * k);
+        let mut expected = start.clone();
+        let mut actual = start;
+        reference::gemm_nn(&delta, &weights, &mut expected, m, n, k);
+        kernel.gemm_nn(&delta, &weights, &mut actual, m, n, k);
+        worst[1] = worst[1].max(max_relative_error(&expected, &actual));
+
+        let start = random_matrix(n * k);
+        let mut expected = start.clone();
+        let mut actual = start;
+        reference::gemm_tn(&delta, &input, &mut expected, m, n, k);
+        kernel.gemm_tn(&delta, &input, &mut actual, m, n, k);
+        worst[2] = worst[2].max(max_relative_error(&expected, &actual));
+    }
+    worst
+}
+
+//runs every kernel this cpu has against the reference loops.
+//returns false if any of them drifted past the tolerance.
+pub fn check_kernels() -> bool {
+    let mut passed = true;
+    for kernel in available() {
+        let worst = worst_errors(kernel).into_iter().fold(0f32, f32::max);
+        let verdict = if worst <= CHECK_TOLERANCE { "ok" } else { passed = false; "FAILED" };
+        println!("{:<8} max relative error {:e}  {verdict}", format!("{kernel:?}"), worst);
+    }
+    passed
+}
+
+fn random_matrix(len: usize) -> Vec<f32> {
+    (0..len)
+        .map(|_| (rand::rng().sample::<f32, _>(StandardUniform) - 0.5) * 2.0)
+        .collect()
+}
+
+fn max_relative_error(expected: &[f32], actual: &[f32]) -> f32 {
+    let mut worst = 0f32;
+    for (e, a) in expected.iter().zip(actual) {
+        let error = (e - a).abs() / e.abs().max(1.0);
+        worst = worst.max(error);
+    }
+    worst
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kernels_match_reference() {
+        for kernel in available() {
+            let worst = worst_errors(kernel);
+            for (name, error) in ["gemm_nt", "gemm_nn", "gemm_tn"].iter().zip(worst) {
+                assert!(error <= CHECK_TOLERANCE, "{kernel:?} {name} off by {error:e}");
+            }
+        }
+    }
+}
@@ -0,0 +1,163 @@
+use std::fmt;
+
+use rand::{distr::StandardUniform, prelude::*};
+
+use crate::model::*;
+
+//how a ring lays its children together before its weights see them
+#[derive(serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Merge {
+    //side by side, the input is as wide as all the children together
+    #[default]
+    Concat,
+    //the rest are elementwise and need children of one width.
+    //the input is as wide as one child.
+    Sum,
+    Mean,
+    Max,
+}
+
+impl std::str::FromStr for Merge {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Merge, ()> {
+        match s {
+            "concat" => Ok(Merge::Concat),
+            "sum" => Ok(Merge::Sum),
+            "mean" => Ok(Merge::Mean),
+            "max" => Ok(Merge::Max),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for Merge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Merge::Concat => write!(f, "concat"),
+            Merge::Sum => write!(f, "sum"),
+            Merge::Mean => write!(f, "mean"),
+            Merge::Max => write!(f, "max"),
+        }
+    }
+}
+
+impl<F: Scalar> Model<F> {
+    pub fn merge(&self, layer: usize, ring: usize) -> Option<Merge> {
+        self.ring_net.get(layer)?.get(ring).map(|ring| ring.merge)
+    }
+    //a sum of a ring and an earlier one is a residual shortcut.
+    //if the input width changes the ring's weights start over.
+    pub fn set_merge(&mut self, layer: usize, ring: usize, merge: Merge) {
+        if layer == 0 || self.merge(layer, ring).is_none() {
+            println!(">no ring at [{layer}][{ring}]");
+            return
+        }
+        match self.ring_net[layer][ring].kind {
+            RingKind::Dense => (),
+            //its width is its parents' input, it can't change under them
+            RingKind::Identity => { println!(">an identity ring's merge is set when it is added"); return },
+            _ => { println!(">conv and pool rings stack their children as channels"); return },
+        }
+        let widths: Vec<usize> = self.ring_net[layer][ring].children.iter()
+            .map(|child| self.ring_net[child.0][child.1].neuron_count)
+            .collect();
+        if merge != Merge::Concat && widths.iter().any(|width| *width != widths[0]) {
+            println!(">{merge} needs children of one width, found {widths:?}");
+            return
+        }
+        let old_count = Self::source_activations_count(&self.ring_net, &self.ring_net[layer][ring]);
+        self.ring_net[layer][ring].merge = merge;
+        let new_count = Self::source_activations_count(&self.ring_net, &self.ring_net[layer][ring]);
+        self.quantized = None;
+        if new_count != old_count {
+            let weight_count = new_count * self.ring_net[layer][ring].neuron_count;
+            self.weight_net[layer][ring] = (0..weight_count)
+                .map(|_| F::of_f32((rand::rng().sample::<f32, _>(StandardUniform) - 0.5 ) * 2.0))
+                .collect();
+            println!(">[{layer}][{ring}] now reads {new_count} inputs, its weights start over");
+        }
+    }
+}
+
+impl<F: Scalar> Model<F> {
+    //a ring with no weights that hands its children on,
+    //side by side or laid together by merge
+    pub fn add_identity_ring(&mut self, layer: usize, merge: Merge, children: Vec<Child>) {
+        if layer == 0 || layer > self.ring_net.len() || children.is_empty() {
+            println!(">an identity ring needs a layer past 0 and children");
+            return
+        }
+        let widths: Vec<usize> = children.iter()
+            .map(|child| self.ring_net[child.0][child.1].neuron_count)
+            .collect();
+        if merge != Merge::Concat && widths.iter().any(|width| *width != widths[0]) {
+            println!(">{merge} needs children of one width, found {widths:?}");
+            return
+        }
+        let neuron_count = match merge {
+            Merge::Concat => widths.iter().sum(),
+            _ => widths[0],
+        };
+        if layer == self.ring_net.len() {
+            self.ring_net.push(Vec::new());
+            self.weight_net.push(Vec::new());
+            self.bias_net.push(Vec::new());
+        }
+        self.quantized = None;
+        self.ring_net[layer].push(Ring {
+            children,
+            neuron_count,
+            regularize: Regularize::default(),
+            kind: RingKind::Identity,
+            merge,
+            trainable: true,
+        });
+        self.weight_net[layer].push(Vec::new());
+        self.bias_net[layer].push(Vec::new());
+        self.settle_norms();
+    }
+}
+
+//the elementwise merges, one image's row at a time.
+//the first child is copied in, the rest fold on top.
+pub(crate) fn merge_into<F: Scalar>(merge: Merge, row: &mut [F], source: &[F], first: bool) {
+    if first {
+        row.copy_from_slice(source);
+        return
+    }
+    for (value, s) in row.iter_mut().zip(source) {
+        match merge {
+            Merge::Sum | Merge::Mean => *value += *s,
+            //strictly greater, so the first child wins a tie
+            Merge::Max => if *s > *value { *value = *s },
+            Merge::Concat => unreachable!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_and_batched_projections_agree() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let images: Vec<Vec<f32>> = (0..20).map(|_| (0..16).map(|_| rng.random::<f32>()).collect()).collect();
+        for merge in [Merge::Concat, Merge::Sum, Merge::Mean, Merge::Max] {
+            //a dense and an identity ring both merging the same two children
+            let mut model = Model::<f32>::new(1, 6, 16);
+            model.add_ring(1, 6, vec![Child(0,0)]);
+            model.add_ring(2, 5, vec![Child(1,0), Child(1,1)]);
+            model.set_merge(2, 0, merge);
+            model.add_identity_ring(2, merge, vec![Child(1,0), Child(1,1)]);
+            model.cap(4);
+            assert_eq!(model.merge(2, 0), Some(merge));
+
+            let mut buffers = ActivationBuffers::new(&model, images.len());
+            let batched = model.projection_batch(&images, &mut buffers);
+            let single: Vec<usize> = images.iter().map(|image| model.projection(image)).collect();
+            assert_eq!(single, batched, "{merge}");
+        }
+    }
+}
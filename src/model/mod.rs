@@ -1,10 +1,52 @@
 mod net_printer;
 mod async_tasks;
+mod projection;
+mod reflection;
+mod kernels;
+mod training;
+mod precision;
+mod scalar;
+mod gradcheck;
+mod preprocess;
+mod schedule;
+mod regularize;
+mod normalize;
+mod convolution;
+mod pooling;
+mod merge;
+mod freeze;
+mod growth;
+mod prune;
+mod shrink;
+mod distill;
+mod confusion;
+mod ensemble;
 
-use std::{fs::File, io::{BufReader, BufWriter, Error, Write}, str::FromStr, thread};
+use std::{fs::File, io::{BufWriter, Error, Write}, str::FromStr, thread};
 use std::fmt;
 use rand::{distr::StandardUniform, prelude::*};
 use crate::model::net_printer::*;
+use crate::model::freeze::trainable_default;
+pub use crate::model::projection::*;
+pub use crate::model::reflection::*;
+pub use crate::model::kernels::check_kernels;
+pub use crate::model::training::*;
+pub use crate::model::precision::*;
+pub use crate::model::scalar::Scalar;
+pub use crate::model::gradcheck::*;
+pub use crate::model::preprocess::*;
+pub use crate::model::schedule::*;
+pub use crate::model::regularize::*;
+pub use crate::model::normalize::*;
+pub use crate::model::convolution::*;
+pub use crate::model::pooling::*;
+pub use crate::model::merge::*;
+pub use crate::model::growth::*;
+pub use crate::model::prune::*;
+pub use crate::model::shrink::*;
+pub use crate::model::distill::*;
+pub use crate::model::confusion::*;
+pub use crate::model::ensemble::*;
 #[allow(unused_imports)]
 use crate::echo;
 
@@ -38,12 +80,38 @@ type Activations<A> = Vec<A>;
 
 // The Model holds all the persistent state in mirrored vector trees.
 // Layers -> Rings -> Neurons
-#[derive(serde::Serialize, serde::Deserialize)]
-pub struct Model {
+// Generic over the number type, f32 unless asked otherwise.
+// Scalar already carries the serde bounds, so derive adds none of its own.
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
+#[serde(bound = "")]
+pub struct Model<F: Scalar = f32> {
     ring_net: Funnel<Layer<Ring>>,
-    weight_net: Funnel<Layer<Weights<f32>>>,
-    bias_net: Funnel<Layer<Biases<f32>>>,
+    weight_net: Funnel<Layer<Weights<F>>>,
+    bias_net: Funnel<Layer<Biases<F>>>,
+    //batch or layer norm per ring, scale and shift beside the biases
+    #[serde(default)]
+    norm_net: Funnel<Layer<Normal<F>>>,
     state: ModelState,
+    //int8 twins of weight_net, only present after quantize
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    quantized: Option<Funnel<Layer<QuantizedRing>>>,
+    //what the raw pixels go through on the way into layer 0
+    #[serde(default)]
+    preprocess: Preprocess,
+    //the learning rate schedule and how far along it training is
+    #[serde(default)]
+    progress: Progress,
+    //compressed twins of the heavily pruned rings, None for the rest
+    #[serde(default)]
+    sparse: Funnel<Layer<Option<SparseRing<F>>>>,
+    //true for every weight prune took, so training leaves it at 0.
+    //empty for rings that were never pruned
+    #[serde(default)]
+    pruned: Funnel<Layer<Vec<bool>>>,
+    //which images training held back, for anything that
+    //needs ones the model never learned from
+    #[serde(default)]
+    split: Split,
 }
 
 pub struct Ticket(u64, Destination);
@@ -66,6 +134,16 @@ pub struct Ring {
     //the number of output "pixels"
     neuron_count: usize, 
     //activation_function: enum,
+    #[serde(default)]
+    regularize: Regularize,
+    #[serde(default)]
+    kind: RingKind,
+    //how the children are laid together
+    #[serde(default)]
+    merge: Merge,
+    //false keeps the ring as it is while the rest trains
+    #[serde(default = "trainable_default")]
+    trainable: bool,
 }
 
 //the answer digits are the parent neurons
@@ -84,22 +162,25 @@ enum ModelState {
     Functional,
 }
 
-impl Model {
-    pub fn startup() -> Model {
+impl<F: Scalar> Model<F> {
+    //input_size is the loaded dataset's pixel count,
+    //used when there's no workbench to pick up
+    pub fn startup(input_size: usize) -> Model<F> {
         let file = String::from_str("workbench").unwrap();
-        if let Ok(model) = Model::load(&file) { model } 
-        else { Model::new(4, 4) }
+        if let Ok(model) = Self::load(&file) { model } 
+        else { Self::new(4, 4, input_size) }
     }
-    pub fn new(depth: usize, width: usize) -> Model {
+    //input_size is the pixel count of the images layer 0 will hold
+    pub fn new(depth: usize, width: usize, input_size: usize) -> Model<F> {
         //create backbone
         let (
             mut ring_net, 
             mut weight_net, 
             mut bias_net
-            ) = Model::new_backbone();
+            ) = Self::new_backbone();
         
         //create initial layers
-        Model::new_initial_layers(&mut ring_net, &mut weight_net, &mut bias_net);
+        Self::new_initial_layers(&mut ring_net, &mut weight_net, &mut bias_net);
         
         //breaking things down into step functions hardly saves me space.
         //I expected visual simplicity:
@@ -112,7 +193,11 @@ impl Model {
         ring_net[0].push(
             Ring {
                 children,
-                neuron_count: 28*28,
+                neuron_count: input_size,
+                regularize: Regularize::default(),
+                kind: RingKind::Dense,
+                merge: Merge::Concat,
+                trainable: true,
             }
         );
         
@@ -129,6 +214,10 @@ impl Model {
             ring_layer.push(Ring {
                     children,
                     neuron_count: width,
+                    regularize: Regularize::default(),
+                    kind: RingKind::Dense,
+                    merge: Merge::Concat,
+                    trainable: true,
                 }
             );
             ring_net.push(ring_layer);
@@ -148,13 +237,13 @@ impl Model {
             //  create weights
             for (mut_layer, read_layer) in weight_net.iter_mut().zip(&ring_net).skip(1) {
                 s.spawn(|| {
-                    let weight_count = Model::source_activations_count(
+                    let weight_count = Self::source_activations_count(
                         &ring_net, 
                         &read_layer[0]
                         ) * read_layer[0].neuron_count;
                     let mut matrix = Vec::new();
                     for _ in 0..weight_count {
-                        matrix.push((rand::rng().sample::<f32, _>(StandardUniform) - 0.5 ) * 2.0);
+                        matrix.push(F::of_f32((rand::rng().sample::<f32, _>(StandardUniform) - 0.5 ) * 2.0));
                     }
                     mut_layer.push(matrix);
                 });
@@ -165,7 +254,7 @@ impl Model {
                     let bias_count = read_layer[0].neuron_count;
                     let mut matrix = Vec::new();
                     for _ in 0..bias_count {
-                        matrix.push((rand::rng().sample::<f32, _>(StandardUniform) - 0.5 ) * 2.0);
+                        matrix.push(F::of_f32((rand::rng().sample::<f32, _>(StandardUniform) - 0.5 ) * 2.0));
                     }
                     mut_layer.push(matrix);
                 });
@@ -179,12 +268,22 @@ impl Model {
             
             // spinlocks would make more sense.
         });
-        Model {
+        let mut model = Model {
             ring_net,
             weight_net,
             bias_net,
+            norm_net: Vec::new(),
             state: ModelState::Malformed,
-        }
+            quantized: None,
+            preprocess: Preprocess::default(),
+            progress: Progress::default(),
+            sparse: Vec::new(),
+            pruned: Vec::new(),
+            split: Split::Untrained,
+        };
+        //every ring starts without normalization
+        model.settle_norms();
+        model
         
         //.. huh... it works. 
         // created 1000x1000 in a second
@@ -206,37 +305,34 @@ impl Model {
     }
     
     pub fn test(&self, data: &Vec<Vec<f32>>, labels: &Vec<u8>) {
-        let mut correct = 0usize;
-        let mut incorrect = 0usize;
-        for index in 0..data.len() {
-            let image = &data[index];
-            let answer = labels[index] as usize;
-            let results = self.projection(&image);
-            if results == answer {
-                correct += 1;
-            } else {
-                incorrect += 1;
+        let mut confusion = Confusion::new(self.class_count().unwrap_or(0));
+        //one set of buffers for the whole run, each ring is one product per batch.
+        let mut buffers = ActivationBuffers::new(self, TEST_BATCH);
+        for (images, answers) in data.chunks(TEST_BATCH).zip(labels.chunks(TEST_BATCH)) {
+            let results = self.projection_batch(images, &mut buffers);
+            for (result, answer) in results.iter().zip(answers) {
+                confusion.add(*answer, *result);
             }
         }
-        println!("correct:   {correct}");
-        println!("incorrect: {incorrect}");
+        println!("{confusion}");
     }
     pub fn save(&self, file: &String) -> Result<(), Error> {
         let mut path = file.clone();
         path.push_str(".json");
         let file = File::create(&path)?;
         let mut writer = BufWriter::new(file);
-        serde_json::to_writer(&mut writer, self)?;
+        let mut value = serde_json::to_value(self)?;
+        self.strip_sparse(&mut value);
+        serde_json::to_writer(&mut writer, &value)?;
         writer.flush()?;
         Ok(())
     }
-    pub fn load(file: &String) -> Result<Model, Error> {
-        let mut path = file.clone();
-        path.push_str(".json");
-        let file = File::open(&path)?;
-        let rdr = BufReader::new(file);
-        let model: Model = serde_json::from_reader(rdr)?;
-        Ok(model)
+    //json numbers have no width, so a file saved from an f64
+    //model loads into an f32 one and back without any fuss.
+    pub fn load(file: &str) -> Result<Model<F>, Error> {
+        //packed files need their weights unpacked first,
+        //plain files pass straight through.
+        Self::load_packed(file)
     }
     pub fn exit(&self) -> ! {
         println!(">shutting down");
@@ -244,21 +340,22 @@ impl Model {
         //shut down sequence
         std::process::exit(0)
     }
+    //the length of one weight row. a conv ring's row is its kernel window.
     pub fn source_activations_count(ring_net: &Vec<Vec<Ring>>, ring: &Ring) -> usize {
-        let children = &ring.children;
-        let mut activations_count = 0;
-        for child in children {
-            activations_count += ring_net[child.0][child.1].neuron_count;
+        match &ring.kind {
+            RingKind::Conv(conv) => return conv.window(),
+            RingKind::Pool(_) | RingKind::Identity => return 0,
+            RingKind::Dense => (),
         }
-        activations_count 
+        Self::gathered_count(ring_net, ring)
     }
     fn new_backbone<T, U, V>() -> (Vec<T>, Vec<U>, Vec<V>) {
         (Vec::new(), Vec::new(), Vec::new())
     }
     fn new_initial_layers(
         ring_net: &mut Vec<Vec<Ring>>, 
-        weight_net: &mut Vec<Vec<Vec<f32>>>,
-        bias_net: &mut Vec<Vec<Vec<f32>>>
+        weight_net: &mut Vec<Vec<Vec<F>>>,
+        bias_net: &mut Vec<Vec<Vec<F>>>
         ) {
         ring_net.push(Vec::new());
         weight_net.push(Vec::new());
@@ -279,24 +376,31 @@ impl Model {
         assert_eq!(&self.ring_net[layer].len(), &self.weight_net[layer].len());
         assert_eq!(&self.ring_net[layer].len(), &self.bias_net[layer].len());
         
+        //any change in shape leaves the int8 twins behind
+        self.quantized = None;
         let ring = Ring {
             children,
             neuron_count,
+            regularize: Regularize::default(),
+            kind: RingKind::Dense,
+            merge: Merge::Concat,
+            trainable: true,
         };
-        let weight_count = Model::source_activations_count(&self.ring_net, &ring);
+        let weight_count = Self::source_activations_count(&self.ring_net, &ring);
         let total_weight_count = weight_count * neuron_count;
         let mut w_matrix = Vec::new();
         let mut b_matrix = Vec::new();
         for _ in 0..total_weight_count {
-            w_matrix.push((rand::rng().sample::<f32, _>(StandardUniform) - 0.5 ) * 2.0);
+            w_matrix.push(F::of_f32((rand::rng().sample::<f32, _>(StandardUniform) - 0.5 ) * 2.0));
         }
         for _ in 0..neuron_count {
-            b_matrix.push((rand::rng().sample::<f32, _>(StandardUniform) - 0.5 ) * 2.0);
+            b_matrix.push(F::of_f32((rand::rng().sample::<f32, _>(StandardUniform) - 0.5 ) * 2.0));
         }
         
         self.ring_net[layer].push(ring);
         self.weight_net[layer].push(w_matrix);
         self.bias_net[layer].push(b_matrix);
+        self.settle_norms();
         
         //how do the properties of rings live and interact?
         //Relu, SoftMax, many_hot, targetting...
@@ -310,10 +414,13 @@ impl Model {
         // That should be the only thing I'm dealing with. 
         
         let (l, r) = ring;
+        //a conv ring's window is fixed to the maps it was built on
+        if self.ring_net[l][r].kind != RingKind::Dense { return }
         let neuron_count = self.ring_net[l][r].neuron_count;
+        self.quantized = None;
 
         let mut old_weights = Vec::new();
-        let old_input_count = Model::source_activations_count(&self.ring_net, &self.ring_net[l][r]);
+        let old_input_count = Self::source_activations_count(&self.ring_net, &self.ring_net[l][r]);
         let mut current_weights = self.weight_net[l][r].chunks_exact(old_input_count);
         for _ in 0..neuron_count {
             let matrix = current_weights.next().unwrap().to_owned();
@@ -324,7 +431,7 @@ impl Model {
             //add child to ring
             self.ring_net[l][r].children.push(child.clone());
         }
-        let new_input_count = Model::source_activations_count(&self.ring_net, &self.ring_net[l][r]);
+        let new_input_count = Self::source_activations_count(&self.ring_net, &self.ring_net[l][r]);
         let additional = new_input_count.checked_sub(old_input_count);
         let additional = additional.unwrap();
         
@@ -332,13 +439,13 @@ impl Model {
         //not sure if it was useful here. 
         let initializer = std::iter::from_fn(
             ||{
-                let new_random_weight = (rand::rng().sample::<f32, _>(StandardUniform) - 0.5 ) * 2.0;
+                let new_random_weight = F::of_f32((rand::rng().sample::<f32, _>(StandardUniform) - 0.5 ) * 2.0);
                 Some(new_random_weight)
             }).take(additional);
             
         let mut new_weights = Vec::new();
         for _ in 0..neuron_count {
-            let matrix = initializer.clone().collect::<Vec<f32>>();
+            let matrix = initializer.clone().collect::<Vec<F>>();
             new_weights.push(matrix);
         }
         let mut collected_weights = Vec::new();
@@ -346,7 +453,7 @@ impl Model {
             collected_weights.push(old_weights[i].clone());
             collected_weights.push(new_weights[i].clone());
         }
-        self.weight_net[l][r] = collected_weights.into_iter().flatten().collect::<Vec<f32>>();
+        self.weight_net[l][r] = collected_weights.into_iter().flatten().collect::<Vec<F>>();
         
     }
     
@@ -366,7 +473,8 @@ impl Model {
     
     
     
-    pub fn cap(&mut self) {
+    //classes is how many answers the cap ring chooses between
+    pub fn cap(&mut self, classes: usize) {
         
         //model.create_new_ring(where, from)
         
@@ -378,73 +486,65 @@ impl Model {
         for i in 0..ring_count {
             children.push(Child(last_layer, i));
         }
-        let neuron_count = 10;
+        let neuron_count = classes;
         let ring = Ring {
             children,
             neuron_count,
+            regularize: Regularize::default(),
+            kind: RingKind::Dense,
+            merge: Merge::Concat,
+            trainable: true,
         };
+        self.quantized = None;
         self.ring_net.push(Vec::new()); //new last layer
         self.ring_net[cap_layer].push(ring); //new solo ring
         
         //fill the weights
         self.weight_net.push(Vec::new());
-        let weight_count = Model::source_activations_count(&self.ring_net, &self.ring_net[cap_layer][0]);
+        let weight_count = Self::source_activations_count(&self.ring_net, &self.ring_net[cap_layer][0]);
         let mut matrix = Vec::new();
-        for _ in 0..neuron_count { // for 10 neurons in our cap ring
+        for _ in 0..neuron_count { // for each neuron in our cap ring
             for _ in 0..weight_count { //multiply weights by neuron count
-                matrix.push((rand::rng().sample::<f32, _>(StandardUniform) - 0.5 ) * 2.0);
+                matrix.push(F::of_f32((rand::rng().sample::<f32, _>(StandardUniform) - 0.5 ) * 2.0));
             }
         }
         self.weight_net[cap_layer].push(matrix);
         //fill biases
-        let matrix = vec![0f32;10];
+        let matrix = vec![F::zero();neuron_count];
         self.bias_net.push(Vec::new());
         self.bias_net[cap_layer].push(matrix);
+        self.settle_norms();
         self.state = ModelState::Functional;
         println!(">Cap layer created");
         self.display_model();
     }
-    pub fn display_model(&self) {
-        let mut net_printer = NetPrinter::new();
-        net_printer.display_net(&self);
+    pub fn split(&self) -> Split {
+        self.split
     }
-    //allocating new activation net for each image
-    //perhaps take in a reference to the dataset
-    //and a range to work through. maybe in test.
-    fn projection(&self, image: &Vec<f32>) -> usize { //refactor because redesign
-        //single wave through funnel
-        
-        //each ring:
-        //workpiece.input()
-        //      which input? defined by children pointers
-        //workpiece.biases()
-        //workpiece.function()
-        //      which function? saved in ring as well.
-        
-        let ring_net = &self.ring_net;
-        // make sure the model is well formed
+    pub fn input_size(&self) -> usize {
+        self.ring_net[0][0].neuron_count
+    }
+    //None until the model is capped
+    pub fn class_count(&self) -> Option<usize> {
         match self.state {
-            ModelState::Malformed => return usize::MAX,
-            _ => (),
+            ModelState::Malformed => None,
+            ModelState::Functional => {
+                let last_layer = self.ring_net.len() - 1;
+                Some(self.ring_net[last_layer][0].neuron_count)
+            },
         }
-        
-        
-        assert_eq!(image.len(), 28*28);
-        let mut activation_net: Funnel<Layer<Activations<f32>>> = Vec::new();
-        let mut initial_layer = Vec::new();
-        initial_layer.push(image.clone());
-        activation_net.push(initial_layer);
-        let mut answer = (usize::MAX, &-1.0f32);
-        answer.0
-        
-        
-        
-        
     }
-    fn reflection(){
-        //wave through funnel that is reflected back
-        //one_hot encoding, with our math for error,
-        //is our mirror. backprop
+    pub fn display_model(&self) {
+        let mut net_printer = NetPrinter::new();
+        net_printer.display_net(self);
+    }
+    //one image as a batch of one, through the same wave as
+    //projection_batch, so merges, norms and int8 rings agree.
+    //allocates its buffers each call, projection_batch
+    //takes a range of the dataset and reuses them.
+    pub fn projection(&self, image: &Vec<f32>) -> usize {
+        let mut buffers = ActivationBuffers::new(self, 1);
+        self.projection_batch(std::slice::from_ref(image), &mut buffers)[0]
     }
 }
 
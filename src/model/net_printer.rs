@@ -49,14 +49,16 @@ impl<'a> NetPrinter<'a> {
     //my methods are line_constructors,
     //which puts the printer into a work lose-able state,
     //or line_enders ie carriage_return,
-    pub fn display_net(&mut self, model: &Model) {
+    pub fn display_net<F: Scalar>(&mut self, model: &Model<F>) {
         for l in 1..model.ring_net.len() {
             for r in 0..model.ring_net[l].len() {
                 self.display_ring(&model, l, r);
             }
             self.commit_bar();
         }
-        
+        let (kept, total) = model.total_parameter_count();
+        self.line.push_str(&format!("{kept}/{total} parameters"));
+        self.commit_line();
     }
     fn clear_line(&mut self) {
         self.last_line_width = self.line.chars().count();
@@ -165,11 +167,53 @@ impl<'a> NetPrinter<'a> {
             self.line.push(' ');
         }
     }
-    fn display_ring(&mut self, model: &Model, l: usize, r: usize) {
+    //a ring with no weights to draw, just its name in a box
+    fn display_box(&mut self, label: &str) {
+        let width = label.chars().count() + 2;
+        let horz = self.horz_char.to_string().repeat(width);
+        for line in [format!("┌{horz}┐"), format!("{} {label} {}", self.vert_char_minor, self.vert_char_minor), format!("└{horz}┘")] {
+            self.push_indent();
+            self.line.push_str(&line);
+            self.commit_line();
+        }
+    }
+    fn display_ring<F: Scalar>(&mut self, model: &Model<F>, l: usize, r: usize) {
         self.line.clear();
         
         self.ring_num = r;
-        let weight_count = Model::source_activations_count(&model.ring_net, &model.ring_net[l][r]);
+        let ring = &model.ring_net[l][r];
+        match &ring.kind {
+            RingKind::Pool(pool) => { self.display_box(&pool.to_string()); return },
+            RingKind::Identity => {
+                let label = format!("identity, {} of {:?}, {} wide", ring.merge, ring.children, ring.neuron_count);
+                self.display_box(&label);
+                return
+            },
+            _ => (),
+        }
+        if !ring.trainable {
+            self.push_indent();
+            self.line.push_str("frozen");
+            self.commit_line();
+        }
+        if ring.merge != Merge::Concat {
+            self.push_indent();
+            self.line.push_str(&format!("{} of {:?}", ring.merge, ring.children));
+            self.commit_line();
+        }
+        //anything else without weights has nothing to draw either
+        if model.weight_net[l][r].is_empty() {
+            self.display_box(&format!("{} neurons, no weights", ring.neuron_count));
+            return
+        }
+        let weight_count = Model::<F>::source_activations_count(&model.ring_net, &model.ring_net[l][r]);
+        //a row per neuron, or per output channel for a conv ring
+        let rows = model.weight_net[l][r].len() / weight_count.max(1);
+        if let RingKind::Conv(conv) = &model.ring_net[l][r].kind {
+            self.push_indent();
+            self.line.push_str(&conv.to_string());
+            self.commit_line();
+        }
         let max = self.max_window_width;
         let window_width = weight_count.clamp(0, max);
         
@@ -185,9 +229,9 @@ impl<'a> NetPrinter<'a> {
             let short = if is_short {window_width - remainder} 
                 else { 0 };
             let width = if is_short { remainder } else { window_width };
-            for _ in 0..model.ring_net[l][r].neuron_count {
-                let nums = &model.weight_net[l][r][t..t+width];
-                self.push_grad(nums);
+            for _ in 0..rows {
+                let nums: Vec<f32> = model.weight_net[l][r][t..t+width].iter().map(|w| w.as_f32()).collect();
+                self.push_grad(&nums);
                 for _ in 0..short {
                     self.push_horz();
                 }
@@ -208,11 +252,43 @@ impl<'a> NetPrinter<'a> {
         self.push_vert_minor();
         
         for bias in &model.bias_net[l][r] {
-            self.push_bias(&bias, window_width);
+            self.push_bias(&bias.as_f32(), window_width);
             self.push_vert_minor();
             if self.is_overflow() { break }
         }
         self.commit_line();
+
+        //scale and shift under the biases, one line each
+        let normal = &model.norm_net[l][r];
+        if normal.kind != NormKind::None {
+            self.push_indent();
+            self.line.push_str(&normal.kind.to_string());
+            self.commit_line();
+            for values in [&normal.scale, &normal.shift] {
+                self.push_indent();
+                self.push_vert_minor();
+                for value in values {
+                    self.push_bias(&value.as_f32(), window_width);
+                    self.push_vert_minor();
+                    if self.is_overflow() { break }
+                }
+                self.commit_line();
+            }
+        }
+
+        let (kept, total) = model.parameter_count(l, r);
+        let sparsity = 100.0 * (1.0 - kept as f32 / total.max(1) as f32);
+        self.push_indent();
+        self.line.push_str(&format!("{kept}/{total} parameters, {sparsity:.1}% sparse"));
+        if model.sparse_ring(l, r).is_some() { self.line.push_str(", csr") }
+        self.commit_line();
+
+        let regularize = model.ring_net[l][r].regularize;
+        if !regularize.is_off() {
+            self.push_indent();
+            self.line.push_str(&regularize.to_string());
+            self.commit_line();
+        }
     }
 }
 
@@ -0,0 +1,263 @@
+use std::fmt;
+
+use crate::model::*;
+
+//keeps the square root away from 0 on a flat ring
+const EPSILON: f32 = 1e-5;
+//how much of the running statistics survive each step
+const MOMENTUM: f32 = 0.9;
+
+//how a ring evens out its outputs before Relu
+#[derive(serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum NormKind {
+    #[default]
+    None,
+    //each neuron across the images of a batch.
+    //a shard is the batch here, its 16 images
+    //are what each worker sees
+    Batch,
+    //each image across the neurons of the ring
+    Layer,
+}
+
+impl std::str::FromStr for NormKind {
+    type Err = ();
+    fn from_str(s: &str) -> Result<NormKind, ()> {
+        match s {
+            "none" => Ok(NormKind::None),
+            "batch" => Ok(NormKind::Batch),
+            "layer" => Ok(NormKind::Layer),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for NormKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NormKind::None => write!(f, "none"),
+            NormKind::Batch => write!(f, "batch norm"),
+            NormKind::Layer => write!(f, "layer norm"),
+        }
+    }
+}
+
+//one ring's normalization, held in norm_net beside the biases.
+//scale and shift are learned like weights, the running
+//statistics are only watched, and stand in for the batch
+//statistics once training is over.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Default)]
+#[serde(bound = "")]
+pub struct Normal<F: Scalar = f32> {
+    pub kind: NormKind,
+    pub(crate) scale: Vec<F>,
+    pub(crate) shift: Vec<F>,
+    #[serde(default)]
+    pub(crate) running_mean: Vec<F>,
+    #[serde(default)]
+    pub(crate) running_variance: Vec<F>,
+}
+
+//what the forward pass leaves for the backward pass, per ring
+#[derive(Clone, Default)]
+pub struct NormBuffers<F: Scalar = f32> {
+    //the outputs after evening out, before scale and shift
+    pub(crate) normed: Vec<F>,
+    //per neuron for batch norm, per image for layer norm
+    pub(crate) deviation: Vec<F>,
+    //this batch's statistics, for the running ones
+    pub(crate) mean: Vec<F>,
+    pub(crate) variance: Vec<F>,
+}
+
+impl<F: Scalar> NormBuffers<F> {
+    pub fn new(normal: &Normal<F>, neuron_count: usize, capacity: usize) -> NormBuffers<F> {
+        if normal.kind == NormKind::None { return NormBuffers::default() }
+        NormBuffers {
+            normed: vec![F::zero(); neuron_count * capacity],
+            deviation: vec![F::zero(); neuron_count.max(capacity)],
+            mean: vec![F::zero(); neuron_count],
+            variance: vec![F::zero(); neuron_count],
+        }
+    }
+}
+
+impl<F: Scalar> Normal<F> {
+    pub fn new(kind: NormKind, neuron_count: usize) -> Normal<F> {
+        let (scale, shift) = match kind {
+            NormKind::None => (Vec::new(), Vec::new()),
+            _ => (vec![F::one(); neuron_count], vec![F::zero(); neuron_count]),
+        };
+        let (running_mean, running_variance) = match kind {
+            NormKind::Batch => (vec![F::zero(); neuron_count], vec![F::one(); neuron_count]),
+            _ => (Vec::new(), Vec::new()),
+        };
+        Normal { kind, scale, shift, running_mean, running_variance }
+    }
+
+    //output is [batch x n], pre-activation. training picks
+    //batch statistics over running ones for batch norm.
+    pub(crate) fn forward(&self, output: &mut [F], n: usize, training: bool, buffers: &mut NormBuffers<F>) {
+        let batch = output.len() / n;
+        let epsilon = F::of_f32(EPSILON);
+        match self.kind {
+            NormKind::None => return,
+            NormKind::Batch => {
+                let count = F::of_f32(batch as f32);
+                for j in 0..n {
+                    let variance = if training {
+                        let mean = (0..batch).map(|b| output[b*n + j]).sum::<F>() / count;
+                        let variance = (0..batch).map(|b| (output[b*n + j] - mean).powi(2)).sum::<F>() / count;
+                        buffers.mean[j] = mean;
+                        buffers.variance[j] = variance;
+                        variance
+                    } else {
+                        self.running_variance[j]
+                    };
+                    buffers.deviation[j] = (variance + epsilon).sqrt();
+                }
+                for b in 0..batch {
+                    for j in 0..n {
+                        let mean = if training { buffers.mean[j] } else { self.running_mean[j] };
+                        buffers.normed[b*n + j] = (output[b*n + j] - mean) / buffers.deviation[j];
+                    }
+                }
+            },
+            NormKind::Layer => {
+                let count = F::of_f32(n as f32);
+                for b in 0..batch {
+                    let row = &output[b*n..(b+1)*n];
+                    let mean = row.iter().cloned().sum::<F>() / count;
+                    let variance = row.iter().map(|z| (*z - mean).powi(2)).sum::<F>() / count;
+                    let deviation = (variance + epsilon).sqrt();
+                    buffers.deviation[b] = deviation;
+                    for (normed, z) in buffers.normed[b*n..(b+1)*n].iter_mut().zip(row) {
+                        *normed = (*z - mean) / deviation;
+                    }
+                }
+            },
+        }
+        for (row, normed) in output.chunks_exact_mut(n).zip(buffers.normed.chunks_exact(n)) {
+            for j in 0..n {
+                row[j] = self.scale[j] * normed[j] + self.shift[j];
+            }
+        }
+    }
+
+    pub(crate) fn convert<G: Scalar>(&self) -> Normal<G> {
+        let convert = |values: &Vec<F>| -> Vec<G> { values.iter().map(|v| <G as num_traits::NumCast>::from(*v).unwrap()).collect() };
+        Normal {
+            kind: self.kind,
+            scale: convert(&self.scale),
+            shift: convert(&self.shift),
+            running_mean: convert(&self.running_mean),
+            running_variance: convert(&self.running_variance),
+        }
+    }
+
+    //delta arrives for the scaled and shifted output and leaves
+    //for the pre-normalization one, collecting scale and shift's share
+    pub(crate) fn backward(
+        &self,
+        delta: &mut [F],
+        n: usize,
+        training: bool,
+        buffers: &NormBuffers<F>,
+        scale_gradient: &mut [F],
+        shift_gradient: &mut [F],
+        ) {
+        if self.kind == NormKind::None { return }
+        let batch = delta.len() / n;
+        for (row, normed) in delta.chunks_exact_mut(n).zip(buffers.normed.chunks_exact(n)) {
+            for j in 0..n {
+                shift_gradient[j] += row[j];
+                scale_gradient[j] += row[j] * normed[j];
+                row[j] *= self.scale[j];
+            }
+        }
+        let normed = &buffers.normed;
+        match self.kind {
+            NormKind::None => (),
+            //running statistics are constants, only the division is left
+            NormKind::Batch if !training => {
+                for row in delta.chunks_exact_mut(n) {
+                    for (d, deviation) in row.iter_mut().zip(&buffers.deviation) { *d /= *deviation }
+                }
+            },
+            NormKind::Batch => {
+                let count = F::of_f32(batch as f32);
+                for j in 0..n {
+                    let sum = (0..batch).map(|b| delta[b*n + j]).sum::<F>();
+                    let dot = (0..batch).map(|b| delta[b*n + j] * normed[b*n + j]).sum::<F>();
+                    for b in 0..batch {
+                        let i = b*n + j;
+                        delta[i] = (delta[i] - sum / count - normed[i] * dot / count) / buffers.deviation[j];
+                    }
+                }
+            },
+            NormKind::Layer => {
+                let count = F::of_f32(n as f32);
+                for b in 0..batch {
+                    let row = &mut delta[b*n..(b+1)*n];
+                    let normed = &normed[b*n..(b+1)*n];
+                    let sum = row.iter().cloned().sum::<F>();
+                    let dot = row.iter().zip(normed).map(|(d, x)| *d * *x).sum::<F>();
+                    for j in 0..n {
+                        row[j] = (row[j] - sum / count - normed[j] * dot / count) / buffers.deviation[b];
+                    }
+                }
+            },
+        }
+    }
+}
+
+impl<F: Scalar> Model<F> {
+    pub fn norm(&self, layer: usize, ring: usize) -> Option<NormKind> {
+        self.norm_net.get(layer)?.get(ring).map(|normal| normal.kind)
+    }
+    //a new kind starts from scale 1 and shift 0
+    pub fn set_norm(&mut self, layer: usize, ring: usize, kind: NormKind) {
+        if layer == 0 || self.norm(layer, ring).is_none() {
+            println!(">no ring at [{layer}][{ring}]");
+            return
+        }
+        if !self.ring_net[layer][ring].kind.has_weights() {
+            println!(">[{layer}][{ring}] has nothing to normalize");
+            return
+        }
+        self.quantized = None;
+        let neuron_count = self.ring_net[layer][ring].neuron_count;
+        self.norm_net[layer][ring] = Normal::new(kind, neuron_count);
+    }
+
+    //files from before normalization have no norm_net,
+    //every ring gets an empty one
+    pub(crate) fn settle_norms(&mut self) {
+        self.norm_net.resize_with(self.ring_net.len(), Vec::new);
+        for (norms, rings) in self.norm_net.iter_mut().zip(&self.ring_net) {
+            norms.resize_with(rings.len(), Normal::default);
+        }
+    }
+
+    //the shards' batch statistics, averaged, nudge the running ones
+    pub(crate) fn track_statistics(&mut self, gradients: &Gradients<F>, shards: usize) {
+        let momentum = F::of_f32(MOMENTUM);
+        let shards = F::of_f32(shards.max(1) as f32);
+        for (l, layer) in self.norm_net.iter_mut().enumerate() {
+            for (r, normal) in layer.iter_mut().enumerate() {
+                //frozen means the running statistics too
+                if normal.kind != NormKind::Batch || !self.ring_net[l][r].trainable { continue }
+                let pairs = [
+                    (&mut normal.running_mean, &gradients.mean_net[l][r]),
+                    (&mut normal.running_variance, &gradients.variance_net[l][r]),
+                ];
+                for (running, observed) in pairs {
+                    for (value, sum) in running.iter_mut().zip(observed) {
+                        *value = momentum * *value + (F::one() - momentum) * *sum / shards;
+                    }
+                }
+            }
+        }
+    }
+}
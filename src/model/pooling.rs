@@ -0,0 +1,183 @@
+use std::fmt;
+
+use crate::model::*;
+
+#[derive(serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PoolMode {
+    Max,
+    Average,
+}
+
+impl std::str::FromStr for PoolMode {
+    type Err = ();
+    fn from_str(s: &str) -> Result<PoolMode, ()> {
+        match s {
+            "max" => Ok(PoolMode::Max),
+            "avg" | "average" => Ok(PoolMode::Average),
+            _ => Err(()),
+        }
+    }
+}
+
+//a ring with nothing to learn. it shrinks each channel of its
+//children's feature maps by taking the max or mean of every window.
+//its weight_net and bias_net entries stay empty.
+//no Relu either, whatever comes in is already past one, or is the image.
+//
+//the output is channel after channel like a conv ring's, so a dense
+//parent just reads it flat, and a conv parent reads it as maps.
+#[derive(serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pool {
+    pub mode: PoolMode,
+    pub window: usize,
+    pub stride: usize,
+    //the stacked children, (channels, height, width)
+    pub input: (usize, usize, usize),
+}
+
+impl Pool {
+    pub fn output_side(&self) -> (usize, usize) {
+        let (_, height, width) = self.input;
+        let side = |length: usize| length.saturating_sub(self.window) / self.stride.max(1) + 1;
+        (side(height), side(width))
+    }
+    pub fn neuron_count(&self) -> usize {
+        let (height, width) = self.output_side();
+        self.input.0 * height * width
+    }
+
+    //calls visit(output index, input index) for every cell of every window
+    fn windows(&self, mut visit: impl FnMut(usize, usize)) {
+        let (channels, height, width) = self.input;
+        let (out_height, out_width) = self.output_side();
+        for c in 0..channels {
+            for oy in 0..out_height {
+                for ox in 0..out_width {
+                    let o = c*out_height*out_width + oy*out_width + ox;
+                    for ky in 0..self.window {
+                        for kx in 0..self.window {
+                            let (y, x) = (oy*self.stride + ky, ox*self.stride + kx);
+                            visit(o, c*height*width + y*width + x);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    pub(crate) fn forward<F: Scalar>(&self, gather: &[F], output: &mut [F], batch: usize) {
+        let (inputs, n) = (self.input_count(), self.neuron_count());
+        let area = F::of_f32((self.window * self.window) as f32);
+        for b in 0..batch {
+            let image = &gather[b*inputs..(b+1)*inputs];
+            let out = &mut output[b*n..(b+1)*n];
+            match self.mode {
+                PoolMode::Max => {
+                    out.fill(F::min_value());
+                    self.windows(|o, i| if image[i] > out[o] { out[o] = image[i] });
+                },
+                PoolMode::Average => {
+                    out.fill(F::zero());
+                    self.windows(|o, i| out[o] += image[i]);
+                    for value in out.iter_mut() { *value /= area }
+                },
+            }
+        }
+    }
+
+    //max hands each window's error to the cell that won it,
+    //the first one on a tie, same as forward kept.
+    //average spreads it evenly. gather_delta comes in zeroed.
+    pub(crate) fn backward<F: Scalar>(&self, gather: &[F], delta: &[F], gather_delta: &mut [F], batch: usize) {
+        let (inputs, n) = (self.input_count(), self.neuron_count());
+        let area = F::of_f32((self.window * self.window) as f32);
+        let mut winners = vec![(usize::MAX, F::min_value()); n];
+        for b in 0..batch {
+            let image = &gather[b*inputs..(b+1)*inputs];
+            let image_delta = &delta[b*n..(b+1)*n];
+            let back = &mut gather_delta[b*inputs..(b+1)*inputs];
+            match self.mode {
+                PoolMode::Max => {
+                    winners.fill((usize::MAX, F::min_value()));
+                    self.windows(|o, i| if winners[o].0 == usize::MAX || image[i] > winners[o].1 { winners[o] = (i, image[i]) });
+                    for (o, (i, _)) in winners.iter().enumerate() {
+                        back[*i] += image_delta[o];
+                    }
+                },
+                PoolMode::Average => self.windows(|o, i| back[i] += image_delta[o] / area),
+            }
+        }
+    }
+
+    fn input_count(&self) -> usize {
+        let (channels, height, width) = self.input;
+        channels * height * width
+    }
+}
+
+impl fmt::Display for Pool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mode = match self.mode {
+            PoolMode::Max => "max",
+            PoolMode::Average => "avg",
+        };
+        let (height, width) = self.output_side();
+        write!(f,
+            "{mode} pool {w}x{w} stride {}, {}x{}x{} -> {}x{height}x{width}",
+            self.stride,
+            self.input.0,
+            self.input.1,
+            self.input.2,
+            self.input.0,
+            w = self.window,
+        )
+    }
+}
+
+impl<F: Scalar> Model<F> {
+    pub fn add_pool_ring(&mut self, layer: usize, mode: PoolMode, window: usize, stride: usize, children: Vec<Child>) {
+        if layer == 0 || layer > self.ring_net.len() || children.is_empty() {
+            println!(">a pool ring needs a layer past 0 and children");
+            return
+        }
+        if window == 0 || stride == 0 {
+            println!(">window and stride must be above 0");
+            return
+        }
+        let maps: Vec<(usize, usize, usize)> = children.iter()
+            .map(|child| Self::feature_map(&self.ring_net, child))
+            .collect();
+        let (_, height, width) = maps[0];
+        if maps.iter().any(|map| map.1 != height || map.2 != width) {
+            println!(">children must share a height and width, found {maps:?}");
+            return
+        }
+        if window > height || window > width {
+            println!(">a {window}x{window} window doesn't fit in {height}x{width}");
+            return
+        }
+        let channels = maps.iter().map(|map| map.0).sum();
+        let pool = Pool { mode, window, stride, input: (channels, height, width) };
+
+        if layer == self.ring_net.len() {
+            self.ring_net.push(Vec::new());
+            self.weight_net.push(Vec::new());
+            self.bias_net.push(Vec::new());
+        }
+        self.quantized = None;
+        println!(">{pool}");
+        self.ring_net[layer].push(Ring {
+            children,
+            neuron_count: pool.neuron_count(),
+            regularize: Regularize::default(),
+            kind: RingKind::Pool(pool),
+            merge: Merge::Concat,
+            trainable: true,
+        });
+        self.weight_net[layer].push(Vec::new());
+        self.bias_net[layer].push(Vec::new());
+        self.settle_norms();
+    }
+}
@@ -0,0 +1,267 @@
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Error, Write};
+
+use half::{bf16, f16};
+use serde_json::Value;
+
+use crate::model::*;
+use crate::model::scalar::convert_net;
+
+//how a saved file keeps its weights.
+//f32 and f64 are plain numbers, whatever the Model holds in memory.
+//the half types keep the raw bits of each value as a u16.
+//int8 keeps only the quantized rings.
+#[derive(serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Storage {
+    F64,
+    F32,
+    F16,
+    Bf16,
+    Int8,
+}
+
+impl std::str::FromStr for Storage {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Storage, ()> {
+        match s {
+            "f64" => Ok(Storage::F64),
+            "f32" => Ok(Storage::F32),
+            "f16" => Ok(Storage::F16),
+            "bf16" => Ok(Storage::Bf16),
+            "int8" => Ok(Storage::Int8),
+            _ => Err(()),
+        }
+    }
+}
+
+//one ring's weights squeezed into a byte each.
+//real = (q - zero) * scale, per ring for the weights.
+//the input side gets its own scale and zero, found by
+//watching the children's activations on calibration images.
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
+pub struct QuantizedRing {
+    weights: Vec<i8>,
+    weight_scale: f32,
+    weight_zero: i32,
+    input_scale: f32,
+    input_zero: i32,
+    //sum of each neuron's quantized weights, saves a pass per product
+    row_sums: Vec<i32>,
+}
+
+impl QuantizedRing {
+    fn new<F: Scalar>(weights: &[F], k: usize, input_range: (f32, f32)) -> QuantizedRing {
+        let (min, max) = weights.iter().fold((0f32, 0f32), |(lo, hi), w| (lo.min(w.as_f32()), hi.max(w.as_f32())));
+        let (weight_scale, weight_zero) = scale_and_zero(min, max, -128, 127);
+        let quantized: Vec<i8> = weights.iter()
+            .map(|w| ((w.as_f32() / weight_scale).round() as i32 + weight_zero).clamp(-128, 127) as i8)
+            .collect();
+        let row_sums = if k == 0 { Vec::new() } else {
+            quantized.chunks_exact(k)
+                .map(|row| row.iter().map(|q| *q as i32).sum())
+                .collect()
+        };
+        let (input_scale, input_zero) = scale_and_zero(input_range.0, input_range.1, 0, 255);
+        QuantizedRing {
+            weights: quantized,
+            weight_scale,
+            weight_zero,
+            input_scale,
+            input_zero,
+            row_sums,
+        }
+    }
+    fn dequantize<F: Scalar>(&self) -> Weights<F> {
+        self.weights.iter()
+            .map(|q| F::of_f32((*q as i32 - self.weight_zero) as f32 * self.weight_scale))
+            .collect()
+    }
+
+    //the int8 twin of kernels::gemm_nt.
+    //sum((qx - zx)(qw - zw)) expands to
+    //sum(qx*qw) - zw*sum(qx) - zx*sum(qw) + k*zx*zw
+    //so only the first term is walked per neuron.
+    pub(crate) fn product<F: Scalar>(&self, input: &[F], out: &mut [F], m: usize, k: usize, n: usize) {
+        let mut row = vec![0u8; k];
+        let rescale = self.input_scale * self.weight_scale;
+        let (zx, zw) = (self.input_zero, self.weight_zero);
+        for i in 0..m {
+            let mut row_sum = 0i32;
+            for (q, x) in row.iter_mut().zip(&input[i*k..(i+1)*k]) {
+                *q = ((x.as_f32() / self.input_scale).round() as i32 + zx).clamp(0, 255) as u8;
+                row_sum += *q as i32;
+            }
+            for j in 0..n {
+                let neuron = &self.weights[j*k..(j+1)*k];
+                let mut acc = 0i32;
+                for (x, w) in row.iter().zip(neuron) {
+                    acc += *x as i32 * *w as i32;
+                }
+                let acc = acc - zw * row_sum - zx * self.row_sums[j] + k as i32 * zx * zw;
+                out[i*n + j] = F::of_f32(acc as f32 * rescale);
+            }
+        }
+    }
+}
+
+//asymmetric range onto [q_min, q_max], always keeping 0.0 exact
+fn scale_and_zero(min: f32, max: f32, q_min: i32, q_max: i32) -> (f32, i32) {
+    let (min, max) = (min.min(0.0), max.max(0.0));
+    let scale = (max - min) / (q_max - q_min) as f32;
+    if scale <= f32::EPSILON { return (1.0, 0) }
+    let zero = (q_min as f32 - min / scale).round() as i32;
+    (scale, zero.clamp(q_min, q_max))
+}
+
+fn pack<F: Scalar>(net: &Funnel<Layer<Vec<F>>>, storage: Storage) -> Funnel<Layer<Vec<u16>>> {
+    net.iter().map(|layer| {
+        layer.iter().map(|entry| {
+            entry.iter().map(|value| match storage {
+                Storage::Bf16 => bf16::from_f32(value.as_f32()).to_bits(),
+                _ => f16::from_f32(value.as_f32()).to_bits(),
+            }).collect()
+        }).collect()
+    }).collect()
+}
+
+fn unpack(net: Funnel<Layer<Vec<u16>>>, storage: Storage) -> Funnel<Layer<Vec<f32>>> {
+    net.into_iter().map(|layer| {
+        layer.into_iter().map(|entry| {
+            entry.into_iter().map(|bits| match storage {
+                Storage::Bf16 => bf16::from_bits(bits).to_f32(),
+                _ => f16::from_bits(bits).to_f32(),
+            }).collect()
+        }).collect()
+    }).collect()
+}
+
+impl<F: Scalar> Model<F> {
+    //post-training quantization. the calibration images are only
+    //pushed forward to learn the range each ring's input moves in.
+    pub fn quantize(&mut self, calibration: &[Vec<f32>]) {
+        if let ModelState::Malformed = self.state {
+            println!(">model is not capped");
+            return
+        }
+        //run in f32 even if an older quantization exists
+        self.quantized = None;
+        //the range of what each ring actually reads, after its children
+        //are merged. a sum or mean of children is not bounded by theirs.
+        let mut ranges: Funnel<Layer<(f32, f32)>> = self.ring_net.iter()
+            .map(|layer| vec![(0f32, 0f32); layer.len()])
+            .collect();
+        let mut buffers = ActivationBuffers::new(self, TEST_BATCH);
+        for images in calibration.chunks(TEST_BATCH) {
+            self.forward_batch(images, &mut buffers);
+            let batch = images.len();
+            let mut gather = std::mem::take(&mut buffers.gather);
+            for (l, layer) in self.ring_net.iter().enumerate().skip(1) {
+                for (r, ring) in layer.iter().enumerate() {
+                    let k = Self::gathered_count(&self.ring_net, ring);
+                    gather_children(&self.ring_net, &buffers.activation_net, ring, batch, &mut gather);
+                    let range = &mut ranges[l][r];
+                    for value in &gather[..batch * k] {
+                        range.0 = range.0.min(value.as_f32());
+                        range.1 = range.1.max(value.as_f32());
+                    }
+                }
+            }
+            buffers.gather = gather;
+        }
+
+        let mut quantized = Vec::new();
+        for (l, layer) in self.ring_net.iter().enumerate() {
+            let mut quantized_layer = Vec::new();
+            if l == 0 {
+                quantized.push(quantized_layer);
+                continue
+            }
+            for (r, ring) in layer.iter().enumerate() {
+                let k = Self::source_activations_count(&self.ring_net, ring);
+                quantized_layer.push(QuantizedRing::new(&self.weight_net[l][r], k, ranges[l][r]));
+            }
+            quantized.push(quantized_layer);
+        }
+        self.quantized = Some(quantized);
+        println!(">quantized to int8 on {} calibration images", calibration.len());
+    }
+    pub fn dequantize(&mut self) {
+        if self.quantized.take().is_some() {
+            println!(">back to f32 inference");
+        }
+    }
+    pub fn is_quantized(&self) -> bool {
+        self.quantized.is_some()
+    }
+
+    //save with the weights in a smaller storage type.
+    //the rest of the model is written as usual, only the
+    //weight and bias trees are swapped out for their packed form.
+    pub fn save_packed(&self, file: &str, storage: Storage) -> Result<u64, Error> {
+        let path = format!("{file}.json");
+        let mut value = serde_json::to_value(self)?;
+        match storage {
+            //plain numbers, converted to the asked width
+            Storage::F64 => {
+                value["weight_net"] = serde_json::to_value(convert_net::<F, f64>(&self.weight_net))?;
+                value["bias_net"] = serde_json::to_value(convert_net::<F, f64>(&self.bias_net))?;
+            },
+            Storage::F32 => {
+                value["weight_net"] = serde_json::to_value(convert_net::<F, f32>(&self.weight_net))?;
+                value["bias_net"] = serde_json::to_value(convert_net::<F, f32>(&self.bias_net))?;
+            },
+            Storage::F16 | Storage::Bf16 => {
+                value["weight_net"] = serde_json::to_value(pack(&self.weight_net, storage))?;
+                value["bias_net"] = serde_json::to_value(pack(&self.bias_net, storage))?;
+            },
+            Storage::Int8 => {
+                if self.quantized.is_none() {
+                    return Err(Error::other("model has not been quantized"))
+                }
+                //the quantized rings are already in the model, the f32 copies go
+                value["weight_net"] = Value::Null;
+            },
+        }
+        self.strip_sparse(&mut value);
+        value["storage"] = serde_json::to_value(storage)?;
+        let file = File::create(&path)?;
+        let mut writer = BufWriter::new(file);
+        serde_json::to_writer(&mut writer, &value)?;
+        writer.flush()?;
+        Ok(fs::metadata(&path)?.len())
+    }
+    //reads any file save or save_packed wrote
+    pub(crate) fn load_packed(file: &str) -> Result<Model<F>, Error> {
+        let path = format!("{file}.json");
+        let file = File::open(&path)?;
+        let rdr = BufReader::new(file);
+        let mut value: Value = serde_json::from_reader(rdr)?;
+        let storage = match value.get("storage") {
+            Some(storage) => serde_json::from_value(storage.clone())?,
+            None => Storage::F32,
+        };
+        match storage {
+            //json numbers land in whichever width F is
+            Storage::F64 | Storage::F32 => (),
+            Storage::F16 | Storage::Bf16 => {
+                let weights = serde_json::from_value(value["weight_net"].take())?;
+                let biases = serde_json::from_value(value["bias_net"].take())?;
+                value["weight_net"] = serde_json::to_value(unpack(weights, storage))?;
+                value["bias_net"] = serde_json::to_value(unpack(biases, storage))?;
+            },
+            Storage::Int8 => {
+                //rebuild the f32 weights from the bytes so the model keeps its shape
+                let quantized: Funnel<Layer<QuantizedRing>> = serde_json::from_value(value["quantized"].clone())?;
+                let weights: Funnel<Layer<Weights<F>>> = quantized.iter()
+                    .map(|layer| layer.iter().map(QuantizedRing::dequantize::<F>).collect())
+                    .collect();
+                value["weight_net"] = serde_json::to_value(weights)?;
+            },
+        }
+        let mut model: Model<F> = serde_json::from_value(value)?;
+        model.settle_norms();
+        model.fill_sparse();
+        Ok(model)
+    }
+}
@@ -0,0 +1,110 @@
+use std::fmt;
+
+use crate::model::*;
+
+//pixels that never move, the corners of a digit, would divide by zero
+const MIN_DEVIATION: f32 = 1e-3;
+
+//what happens to a raw pixel before layer 0 sees it.
+//saved with the model, so a loaded model reads its
+//inputs the same way it did while it was trained.
+//applied in order: invert, binarize, standardize.
+#[derive(serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, Default)]
+pub struct Preprocess {
+    //1 - pixel, for light digits on dark paper the other way round
+    pub invert: bool,
+    //pixels above become 1, the rest 0
+    pub threshold: Option<f32>,
+    //per pixel, learned from the training set. empty is off
+    pub mean: Vec<f32>,
+    pub deviation: Vec<f32>,
+}
+
+impl Preprocess {
+    //one pixel, by its index in the image
+    pub fn pixel(&self, i: usize, value: f32) -> f32 {
+        let mut value = if self.invert { 1.0 - value } else { value };
+        if let Some(threshold) = self.threshold {
+            value = if value > threshold { 1.0 } else { 0.0 };
+        }
+        if !self.mean.is_empty() {
+            value = (value - self.mean[i]) / self.deviation[i];
+        }
+        value
+    }
+
+    //the mean and deviation are taken after invert and
+    //binarize, since that is what they'll be standardizing
+    fn fit(&mut self, data: &[Vec<f32>]) {
+        let pixels = data.first().map(|image| image.len()).unwrap_or(0);
+        let (invert, threshold) = (self.invert, self.threshold);
+        let early = Preprocess { invert, threshold, ..Preprocess::default() };
+        let mut sum = vec![0f64; pixels];
+        let mut square_sum = vec![0f64; pixels];
+        for image in data {
+            for (i, value) in image.iter().enumerate() {
+                let value = early.pixel(i, *value) as f64;
+                sum[i] += value;
+                square_sum[i] += value * value;
+            }
+        }
+        let count = data.len().max(1) as f64;
+        self.mean = sum.iter().map(|s| (s / count) as f32).collect();
+        self.deviation = sum.iter().zip(&square_sum)
+            .map(|(s, sq)| {
+                let mean = s / count;
+                ((sq / count - mean * mean).max(0.0).sqrt() as f32).max(MIN_DEVIATION)
+            })
+            .collect();
+    }
+}
+
+impl fmt::Display for Preprocess {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let threshold = match self.threshold {
+            Some(threshold) => threshold.to_string(),
+            None => String::from("off"),
+        };
+        let standardize = if self.mean.is_empty() { "off" } else { "on" };
+        write!(f, "invert: {}, binarize: {threshold}, standardize: {standardize}", self.invert)
+    }
+}
+
+impl<F: Scalar> Model<F> {
+    pub fn preprocess(&self) -> &Preprocess {
+        &self.preprocess
+    }
+    //every change below moves what layer 0 sees,
+    //so int8 input ranges calibrated before no longer hold
+    pub fn set_invert(&mut self, invert: bool) {
+        self.quantized = None;
+        self.preprocess.invert = invert;
+        //the statistics were taken the other way round
+        self.drop_statistics();
+    }
+    pub fn set_threshold(&mut self, threshold: Option<f32>) {
+        self.quantized = None;
+        self.preprocess.threshold = threshold;
+        self.drop_statistics();
+    }
+    pub fn standardize(&mut self, data: &[Vec<f32>]) {
+        if data.first().map(|image| image.len()) != Some(self.input_size()) {
+            println!(">images don't match the model's {} inputs", self.input_size());
+            return
+        }
+        self.quantized = None;
+        self.preprocess.fit(data);
+        println!(">standardized on {} images", data.len());
+    }
+    pub fn unstandardize(&mut self) {
+        self.quantized = None;
+        self.preprocess.mean.clear();
+        self.preprocess.deviation.clear();
+    }
+    fn drop_statistics(&mut self) {
+        if self.preprocess.mean.is_empty() { return }
+        self.unstandardize();
+        println!(">standardization cleared, run standardize again");
+    }
+}
@@ -0,0 +1,379 @@
+use std::time::Instant;
+
+use rand::rngs::StdRng;
+
+use crate::model::*;
+use crate::model::kernels;
+
+//how many images test pushes through the funnel at once
+pub const TEST_BATCH: usize = 256;
+//how many images the bench runs through each path
+const BENCH_IMAGES: usize = 2048;
+
+//one buffer per ring, mirroring the ring_net.
+//each buffer holds the whole batch, one image after the other,
+//so a ring's output is a [batch x neuron_count] matrix.
+//allocated once and reused for every batch that fits.
+pub struct ActivationBuffers<F: Scalar = f32> {
+    capacity: usize,
+    pub(crate) activation_net: Funnel<Layer<Activations<F>>>,
+    //the error arriving at each ring's output on the way back
+    pub(crate) delta_net: Funnel<Layer<Activations<F>>>,
+    //the children of a ring laid side by side, [batch x gathered_count]
+    pub(crate) gather: Vec<F>,
+    //the error for that same side by side input
+    pub(crate) gather_delta: Vec<F>,
+    //set only while training, turns on the rings' dropout
+    //and batch norm's batch statistics
+    pub(crate) dropout: Option<StdRng>,
+    //what each normalized ring keeps for the way back
+    pub(crate) norm_net: Funnel<Layer<NormBuffers<F>>>,
+}
+
+impl<F: Scalar> ActivationBuffers<F> {
+    pub fn new(model: &Model<F>, capacity: usize) -> ActivationBuffers<F> {
+        let mut activation_net = Vec::new();
+        let mut widest = 0;
+        for layer in &model.ring_net {
+            let mut buffer_layer = Vec::new();
+            for ring in layer {
+                buffer_layer.push(vec![F::zero(); ring.neuron_count * capacity]);
+                let width = Model::<F>::gathered_count(&model.ring_net, ring);
+                widest = widest.max(width);
+            }
+            activation_net.push(buffer_layer);
+        }
+        ActivationBuffers {
+            capacity,
+            delta_net: activation_net.clone(),
+            activation_net,
+            gather: vec![F::zero(); widest * capacity],
+            gather_delta: vec![F::zero(); widest * capacity],
+            dropout: None,
+            norm_net: model.ring_net.iter().zip(&model.norm_net)
+                .map(|(rings, normals)| {
+                    rings.iter().zip(normals)
+                        .map(|(ring, normal)| NormBuffers::new(normal, ring.neuron_count, capacity))
+                        .collect()
+                })
+                .collect(),
+        }
+    }
+}
+
+impl<F: Scalar> Model<F> {
+    //the batched wave through the funnel.
+    //every ring is a single matrix product over the whole batch
+    //instead of one dot product per neuron per image.
+    pub fn projection_batch<I: AsRef<[f32]>>(&self, images: &[I], buffers: &mut ActivationBuffers<F>) -> Vec<usize> {
+        if let ModelState::Malformed = self.state {
+            return vec![usize::MAX; images.len()]
+        }
+        let batch = images.len();
+        self.forward_batch(images, buffers);
+
+        let last_layer = self.ring_net.len() - 1;
+        let n = self.ring_net[last_layer][0].neuron_count;
+        let output = &buffers.activation_net[last_layer][0][..batch*n];
+        output.chunks_exact(n).map(argmax::<F>).collect()
+    }
+    //share of the images answered right, 0 to 1
+    pub fn accuracy(&self, data: &[Vec<f32>], labels: &[u8]) -> f32 {
+        let mut buffers = ActivationBuffers::new(self, TEST_BATCH);
+        let mut correct = 0usize;
+        for (images, answers) in data.chunks(TEST_BATCH).zip(labels.chunks(TEST_BATCH)) {
+            let results = self.projection_batch(images, &mut buffers);
+            correct += results.iter().zip(answers).filter(|(result, answer)| **result == **answer as usize).count();
+        }
+        correct as f32 / data.len().max(1) as f32
+    }
+    pub(crate) fn forward_batch<I: AsRef<[f32]>>(&self, images: &[I], buffers: &mut ActivationBuffers<F>) {
+        let ring_net = &self.ring_net;
+        let batch = images.len();
+        assert!(batch <= buffers.capacity);
+
+        //layer 0 is the images themselves, as the model preprocesses them
+        let pixels = ring_net[0][0].neuron_count;
+        let input = &mut buffers.activation_net[0][0];
+        for (b, image) in images.iter().enumerate() {
+            let image = image.as_ref();
+            assert_eq!(image.len(), pixels);
+            for (i, (value, pixel)) in input[b*pixels..(b+1)*pixels].iter_mut().zip(image).enumerate() {
+                *value = F::of_f32(self.preprocess.pixel(i, *pixel));
+            }
+        }
+
+        let last_layer = ring_net.len() - 1;
+        for l in 1..ring_net.len() {
+            for r in 0..ring_net[l].len() {
+                let ring = &ring_net[l][r];
+                let k = Self::gathered_count(ring_net, ring);
+                let n = ring.neuron_count;
+
+                //line the children up side by side for every image
+                let mut gather = std::mem::take(&mut buffers.gather);
+                gather_children(ring_net, &buffers.activation_net, ring, batch, &mut gather);
+
+                let output = &mut buffers.activation_net[l][r][..batch*n];
+                let biases = &self.bias_net[l][r];
+                match (&ring.kind, &self.quantized) {
+                    //nothing learned, nothing more to do
+                    (RingKind::Pool(pool), _) => {
+                        pool.forward(&gather[..batch*k], output, batch);
+                        buffers.gather = gather;
+                        continue
+                    },
+                    (RingKind::Identity, _) => {
+                        output.copy_from_slice(&gather[..batch*k]);
+                        buffers.gather = gather;
+                        continue
+                    },
+                    //conv rings stay in floats even in an int8 model
+                    (RingKind::Conv(conv), _) => {
+                        conv.forward(&gather[..batch*k], &self.weight_net[l][r], output, batch);
+                        let positions = n / conv.channels;
+                        for image in output.chunks_exact_mut(n) {
+                            for (channel, bias) in image.chunks_exact_mut(positions).zip(biases) {
+                                for value in channel.iter_mut() { *value += *bias }
+                            }
+                        }
+                    },
+                    (RingKind::Dense, quantized) => {
+                        match (quantized, self.sparse_ring(l, r)) {
+                            (Some(quantized), _) => quantized[l][r].product(&gather[..batch*k], output, batch, k, n),
+                            (None, Some(sparse)) => sparse.product(&gather[..batch*k], output, batch),
+                            (None, None) => F::gemm_nt(&gather[..batch*k], &self.weight_net[l][r], output, batch, k, n),
+                        }
+                        for row in output.chunks_exact_mut(n) {
+                            for (value, bias) in row.iter_mut().zip(biases) {
+                                *value += *bias;
+                            }
+                        }
+                    },
+                }
+                let training = buffers.dropout.is_some();
+                self.norm_net[l][r].forward(output, n, training, &mut buffers.norm_net[l][r]);
+                if l != last_layer {
+                    for value in output.iter_mut() {
+                        *value = value.max(F::zero());
+                    }
+                }
+                let rate = ring.regularize.dropout;
+                if let Some(rng) = buffers.dropout.as_mut().filter(|_| rate > 0.0 && l != last_layer) {
+                    drop_out(output, rate, rng);
+                }
+                buffers.gather = gather;
+            }
+        }
+    }
+
+    //the unbatched wave, what bench measures the batched one against.
+    //a fresh activation net for each image and one dot product
+    //per neuron, always on the f32 weights.
+    fn projection_per_image(&self, image: &[f32]) -> usize {
+        let ring_net = &self.ring_net;
+        let mut activation_net: Funnel<Layer<Activations<F>>> = vec![vec![
+            image.iter().enumerate().map(|(i, p)| F::of_f32(self.preprocess.pixel(i, *p))).collect()
+        ]];
+        let last_layer = ring_net.len() - 1;
+        for l in 1..ring_net.len() {
+            let mut layer = Vec::new();
+            for r in 0..ring_net[l].len() {
+                let ring = &ring_net[l][r];
+                let mut input = vec![F::zero(); Self::gathered_count(ring_net, ring)];
+                gather_children(ring_net, &activation_net, ring, 1, &mut input);
+                let weights = &self.weight_net[l][r];
+                let biases = &self.bias_net[l][r];
+                let k = input.len();
+                let mut output = vec![F::zero(); ring.neuron_count];
+                match &ring.kind {
+                    RingKind::Conv(conv) => {
+                        conv.forward(&input, weights, &mut output, 1);
+                        let positions = ring.neuron_count / conv.channels;
+                        for (channel, bias) in output.chunks_exact_mut(positions).zip(biases) {
+                            for value in channel.iter_mut() { *value += *bias }
+                        }
+                    },
+                    RingKind::Pool(pool) => pool.forward(&input, &mut output, 1),
+                    RingKind::Identity => output = input,
+                    RingKind::Dense => for (n, value) in output.iter_mut().enumerate() {
+                        let row = &weights[n*k..(n+1)*k];
+                        *value = biases[n] + row.iter().zip(&input).map(|(w, a)| *w * *a).sum::<F>();
+                    },
+                }
+                //pool and identity rings pass their input on untouched
+                if ring.kind.has_weights() {
+                    let normal = &self.norm_net[l][r];
+                    let mut norm_buffers = NormBuffers::new(normal, ring.neuron_count, 1);
+                    normal.forward(&mut output, ring.neuron_count, false, &mut norm_buffers);
+                    if l != last_layer {
+                        for value in output.iter_mut() { *value = value.max(F::zero()) }
+                    }
+                }
+                layer.push(output);
+            }
+            activation_net.push(layer);
+        }
+        argmax(&activation_net[last_layer][0])
+    }
+
+    //throughput of the two forward paths over the same images
+    pub fn bench(&self, data: &[Vec<f32>]) {
+        if let ModelState::Malformed = self.state {
+            println!(">model is not capped");
+            return
+        }
+        let count = data.len().min(BENCH_IMAGES);
+        println!(">kernel: {:?} ({})", kernels::detected(), F::NAME);
+        let images = &data[..count];
+
+        let start = Instant::now();
+        for image in images {
+            std::hint::black_box(self.projection_per_image(image));
+        }
+        let single = start.elapsed();
+
+        let start = Instant::now();
+        let mut buffers = ActivationBuffers::new(self, TEST_BATCH);
+        for chunk in images.chunks(TEST_BATCH) {
+            std::hint::black_box(self.projection_batch(chunk, &mut buffers));
+        }
+        let batched = start.elapsed();
+
+        let single_rate = count as f64 / single.as_secs_f64();
+        let batched_rate = count as f64 / batched.as_secs_f64();
+        println!(">{count} images");
+        println!("per image: {:>10.0} images/s  ({single:?})", single_rate);
+        println!("batched:   {:>10.0} images/s  ({batched:?})", batched_rate);
+        println!("speedup:   {:>10.2}x", batched_rate / single_rate);
+    }
+}
+
+//copies each child's rows into its columns of the gather matrix
+pub(crate) fn gather_children<F: Scalar>(
+    ring_net: &[Vec<Ring>],
+    activation_net: &Funnel<Layer<Activations<F>>>,
+    ring: &Ring,
+    batch: usize,
+    gather: &mut [F],
+    ) {
+    let k = Model::<F>::gathered_count(ring_net, ring);
+    if ring.merge != Merge::Concat {
+        for b in 0..batch {
+            let row = &mut gather[b*k..(b+1)*k];
+            for (i, child) in ring.children.iter().enumerate() {
+                merge_into(ring.merge, row, &activation_net[child.0][child.1][b*k..(b+1)*k], i == 0);
+            }
+            if ring.merge == Merge::Mean {
+                let count = F::of_f32(ring.children.len() as f32);
+                for value in row.iter_mut() { *value /= count }
+            }
+        }
+        return
+    }
+    let mut offset = 0;
+    for child in &ring.children {
+        let width = ring_net[child.0][child.1].neuron_count;
+        let source = &activation_net[child.0][child.1];
+        for b in 0..batch {
+            let row = &mut gather[b*k + offset..b*k + offset + width];
+            row.copy_from_slice(&source[b*width..(b+1)*width]);
+        }
+        offset += width;
+    }
+}
+
+//the way back for gather_children, adds each child's share
+//of gather_delta into its delta. layer 0 has nothing to learn.
+pub(crate) fn scatter_children<F: Scalar>(
+    ring_net: &[Vec<Ring>],
+    activation_net: &Funnel<Layer<Activations<F>>>,
+    delta_net: &mut Funnel<Layer<Activations<F>>>,
+    ring: &Ring,
+    batch: usize,
+    gather_delta: &[F],
+    ) {
+    let k = Model::<F>::gathered_count(ring_net, ring);
+    match ring.merge {
+        Merge::Concat => {
+            let mut offset = 0;
+            for child in &ring.children {
+                let width = ring_net[child.0][child.1].neuron_count;
+                if child.0 != 0 {
+                    let child_delta = &mut delta_net[child.0][child.1];
+                    for b in 0..batch {
+                        let source = &gather_delta[b*k + offset..b*k + offset + width];
+                        for (d, s) in child_delta[b*width..(b+1)*width].iter_mut().zip(source) {
+                            *d += *s;
+                        }
+                    }
+                }
+                offset += width;
+            }
+        },
+        //every child moved the sum one for one
+        Merge::Sum | Merge::Mean => {
+            let share = if ring.merge == Merge::Mean {
+                F::one() / F::of_f32(ring.children.len() as f32)
+            } else {
+                F::one()
+            };
+            for child in ring.children.iter().filter(|child| child.0 != 0) {
+                let child_delta = &mut delta_net[child.0][child.1];
+                for (d, s) in child_delta[..batch*k].iter_mut().zip(&gather_delta[..batch*k]) {
+                    *d += *s * share;
+                }
+            }
+        },
+        //only the child that won each element moved it
+        Merge::Max => {
+            for i in 0..batch*k {
+                let mut winner = &ring.children[0];
+                for child in &ring.children[1..] {
+                    if activation_net[child.0][child.1][i] > activation_net[winner.0][winner.1][i] {
+                        winner = child;
+                    }
+                }
+                if winner.0 != 0 {
+                    delta_net[winner.0][winner.1][i] += gather_delta[i];
+                }
+            }
+        },
+    }
+}
+
+pub fn argmax<F: Scalar>(values: &[F]) -> usize {
+    let mut answer = (usize::MAX, F::min_value());
+    for (i, value) in values.iter().enumerate() {
+        if *value > answer.1 {
+            answer = (i, *value);
+        }
+    }
+    answer.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn bench_paths_agree() {
+        let mut rng = StdRng::seed_from_u64(4);
+        let images: Vec<Vec<f32>> = (0..20).map(|_| (0..36).map(|_| rng.random::<f32>()).collect()).collect();
+        let mut model = Model::<f32>::new(1, 6, 36);
+        model.add_conv_ring(1, 3, 1, 1, 2, vec![Child(0,0)]);
+        model.add_pool_ring(2, PoolMode::Max, 2, 2, vec![Child(1,1)]);
+        model.add_ring(1, 6, vec![Child(0,0)]);
+        model.add_ring(2, 6, vec![Child(1,0), Child(1,2)]);
+        model.set_merge(2, 1, Merge::Sum);
+        model.add_identity_ring(2, Merge::Max, vec![Child(1,0), Child(1,2)]);
+        model.set_norm(2, 1, NormKind::Layer);
+        model.cap(4);
+
+        let mut buffers = ActivationBuffers::new(&model, images.len());
+        let batched = model.projection_batch(&images, &mut buffers);
+        let single: Vec<usize> = images.iter().map(|image| model.projection_per_image(image)).collect();
+        assert_eq!(single, batched);
+    }
+}
@@ -0,0 +1,345 @@
+use serde_json::Value;
+
+use crate::model::*;
+
+//rings left with at most this share of their weights go sparse.
+//past it the dense product is quicker than walking the rows.
+const SPARSE_DENSITY: f32 = 0.3;
+
+//where the cut for the smallest weights is drawn
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PruneScope {
+    //one cut across every ring, big rings can lose more than small ones
+    Global,
+    //each ring loses the same share of its own weights
+    PerRing,
+}
+
+impl std::str::FromStr for PruneScope {
+    type Err = ();
+    fn from_str(s: &str) -> Result<PruneScope, ()> {
+        match s {
+            "global" => Ok(PruneScope::Global),
+            "ring" | "per ring" => Ok(PruneScope::PerRing),
+            _ => Err(()),
+        }
+    }
+}
+
+//a heavily pruned dense ring's weights, compressed sparse rows.
+//only the kept weights are stored, neuron after neuron.
+//weight_net still holds the dense copy with its zeros, this is
+//its twin for the products, and what a save writes in its place.
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
+#[serde(bound = "")]
+pub struct SparseRing<F: Scalar = f32> {
+    //the length of a dense weight row
+    k: usize,
+    //where each neuron's weights start, one more for the end
+    offsets: Vec<usize>,
+    columns: Vec<u32>,
+    values: Vec<F>,
+}
+
+impl<F: Scalar> SparseRing<F> {
+    fn new(weights: &[F], k: usize) -> SparseRing<F> {
+        let mut offsets = vec![0];
+        let mut columns = Vec::new();
+        let mut values = Vec::new();
+        for row in weights.chunks_exact(k) {
+            for (c, w) in row.iter().enumerate() {
+                if *w != F::zero() {
+                    columns.push(c as u32);
+                    values.push(*w);
+                }
+            }
+            offsets.push(values.len());
+        }
+        SparseRing { k, offsets, columns, values }
+    }
+    fn rows(&self) -> usize {
+        self.offsets.len() - 1
+    }
+    fn dense(&self) -> Weights<F> {
+        let mut weights = vec![F::zero(); self.rows() * self.k];
+        for j in 0..self.rows() {
+            for i in self.offsets[j]..self.offsets[j+1] {
+                weights[j*self.k + self.columns[i] as usize] = self.values[i];
+            }
+        }
+        weights
+    }
+    //true wherever a weight was pruned, what the mask of a
+    //sparse ring would say. saved files only keep this side of it.
+    fn mask(&self) -> Vec<bool> {
+        let mut mask = vec![true; self.rows() * self.k];
+        for j in 0..self.rows() {
+            for i in self.offsets[j]..self.offsets[j+1] {
+                mask[j*self.k + self.columns[i] as usize] = false;
+            }
+        }
+        mask
+    }
+    //training moved the dense copy, the kept positions follow it
+    fn refresh(&mut self, weights: &[F]) {
+        for j in 0..self.rows() {
+            for i in self.offsets[j]..self.offsets[j+1] {
+                self.values[i] = weights[j*self.k + self.columns[i] as usize];
+            }
+        }
+    }
+    pub(crate) fn convert<G: Scalar>(&self) -> SparseRing<G> {
+        SparseRing {
+            k: self.k,
+            offsets: self.offsets.clone(),
+            columns: self.columns.clone(),
+            values: self.values.iter().map(|v| <G as num_traits::NumCast>::from(*v).unwrap()).collect(),
+        }
+    }
+
+    //the sparse twin of gemm_nt, input [batch x k] against
+    //the kept weights, written over out [batch x n]
+    pub(crate) fn product(&self, input: &[F], out: &mut [F], batch: usize) {
+        let (k, n) = (self.k, self.rows());
+        for b in 0..batch {
+            let row = &input[b*k..(b+1)*k];
+            for j in 0..n {
+                let mut sum = F::zero();
+                for i in self.offsets[j]..self.offsets[j+1] {
+                    sum += self.values[i] * row[self.columns[i] as usize];
+                }
+                out[b*n + j] = sum;
+            }
+        }
+    }
+    //adds into the kept weights' gradients, the pruned ones stay 0
+    //so they stay pruned. gather_delta is added into like gemm_nn.
+    pub(crate) fn backward(
+        &self,
+        delta: &[F],
+        gather: &[F],
+        weight_gradient: Option<&mut [F]>,
+        gather_delta: &mut [F],
+        batch: usize,
+        ) {
+        let (k, n) = (self.k, self.rows());
+        let mut weight_gradient = weight_gradient;
+        for b in 0..batch {
+            let row = &gather[b*k..(b+1)*k];
+            let back = &mut gather_delta[b*k..(b+1)*k];
+            for j in 0..n {
+                let d = delta[b*n + j];
+                for i in self.offsets[j]..self.offsets[j+1] {
+                    let c = self.columns[i] as usize;
+                    back[c] += d * self.values[i];
+                    if let Some(gradient) = weight_gradient.as_deref_mut() {
+                        gradient[j*k + c] += d * row[c];
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<F: Scalar> Model<F> {
+    //the sparse twin, if it still fits the ring.
+    //a ring that changed shape since goes back to dense products.
+    pub(crate) fn sparse_ring(&self, layer: usize, ring: usize) -> Option<&SparseRing<F>> {
+        let sparse = self.sparse.get(layer)?.get(ring)?.as_ref()?;
+        let ring = &self.ring_net[layer][ring];
+        let k = Self::source_activations_count(&self.ring_net, ring);
+        (sparse.k == k && sparse.rows() == ring.neuron_count).then_some(sparse)
+    }
+
+    //zeroes the smallest weights of the dense rings, share 0.0 - 1.0.
+    //weights already at 0 count toward the share, and every zero
+    //is masked so training can't grow it back.
+    //conv kernels are small enough to leave alone, frozen rings keep theirs.
+    pub fn prune(&mut self, share: f32, scope: PruneScope) {
+        if let ModelState::Malformed = self.state {
+            println!(">model is not capped");
+            return
+        }
+        let share = share.clamp(0.0, 1.0);
+        let rings: Vec<(usize, usize)> = self.ring_net.iter().enumerate().skip(1)
+            .flat_map(|(l, layer)| layer.iter().enumerate().map(move |(r, ring)| (l, r, ring)))
+            .filter(|(_, _, ring)| ring.kind == RingKind::Dense && ring.trainable)
+            .map(|(l, r, _)| (l, r))
+            .collect();
+        //the largest magnitude that goes, None if nothing does
+        let cut = |mut magnitudes: Vec<f32>| -> Option<f32> {
+            let count = (magnitudes.len() as f32 * share) as usize;
+            if count == 0 { return None }
+            let (_, nth, _) = magnitudes.select_nth_unstable_by(count - 1, |a, b| a.total_cmp(b));
+            Some(*nth)
+        };
+        let magnitudes = |weights: &Weights<F>| -> Vec<f32> { weights.iter().map(|w| w.as_f32().abs()).collect() };
+        let global = match scope {
+            PruneScope::Global => cut(rings.iter().flat_map(|(l, r)| magnitudes(&self.weight_net[*l][*r])).collect()),
+            PruneScope::PerRing => None,
+        };
+        self.quantized = None;
+        self.settle_pruned();
+        for (l, r) in &rings {
+            let threshold = match scope {
+                PruneScope::Global => global,
+                PruneScope::PerRing => cut(magnitudes(&self.weight_net[*l][*r])),
+            };
+            let Some(threshold) = threshold else { continue };
+            for w in self.weight_net[*l][*r].iter_mut() {
+                if w.as_f32().abs() <= threshold { *w = F::zero() }
+            }
+            self.pruned[*l][*r] = self.weight_net[*l][*r].iter().map(|w| *w == F::zero()).collect();
+        }
+        self.settle_sparse();
+
+        let (kept, total) = self.total_parameter_count();
+        let sparse = self.sparse.iter().flatten().filter(|sparse| sparse.is_some()).count();
+        println!(">{kept} of {total} parameters left, {sparse} rings sparse");
+    }
+
+    //every dense ring at or under SPARSE_DENSITY gets a twin, the rest lose theirs
+    pub(crate) fn settle_sparse(&mut self) {
+        self.sparse = self.ring_net.iter().enumerate()
+            .map(|(l, layer)| {
+                layer.iter().enumerate().map(|(r, ring)| {
+                    //layer 0 is the image, it has no weights to look at
+                    if l == 0 || ring.kind != RingKind::Dense { return None }
+                    let weights = &self.weight_net[l][r];
+                    if weights.is_empty() { return None }
+                    let kept = weights.iter().filter(|w| **w != F::zero()).count();
+                    if kept as f32 > weights.len() as f32 * SPARSE_DENSITY { return None }
+                    let k = Self::source_activations_count(&self.ring_net, ring);
+                    Some(SparseRing::new(weights, k))
+                }).collect()
+            })
+            .collect();
+    }
+    //one mask slot per ring, a new ring starts unmasked
+    fn settle_pruned(&mut self) {
+        self.pruned.resize_with(self.ring_net.len(), Vec::new);
+        for (masks, rings) in self.pruned.iter_mut().zip(&self.ring_net) {
+            masks.resize_with(rings.len(), Vec::new);
+        }
+    }
+    pub(crate) fn refresh_sparse(&mut self) {
+        for l in 0..self.sparse.len() {
+            for r in 0..self.sparse[l].len() {
+                if self.sparse_ring(l, r).is_none() { continue }
+                if let Some(sparse) = self.sparse[l][r].as_mut() {
+                    sparse.refresh(&self.weight_net[l][r]);
+                }
+            }
+        }
+    }
+
+    //(nonzero, all) weights and biases of one ring
+    pub fn parameter_count(&self, layer: usize, ring: usize) -> (usize, usize) {
+        let weights = &self.weight_net[layer][ring];
+        let biases = &self.bias_net[layer][ring];
+        let kept = weights.iter().chain(biases).filter(|v| **v != F::zero()).count();
+        (kept, weights.len() + biases.len())
+    }
+    pub fn total_parameter_count(&self) -> (usize, usize) {
+        let mut counts = (0, 0);
+        for (l, layer) in self.ring_net.iter().enumerate().skip(1) {
+            for r in 0..layer.len() {
+                let (kept, total) = self.parameter_count(l, r);
+                counts = (counts.0 + kept, counts.1 + total);
+            }
+        }
+        counts
+    }
+
+    //a saved sparse ring keeps only its twin, the dense copy
+    //and the mask are left empty, the columns say the same
+    pub(crate) fn strip_sparse(&self, value: &mut Value) {
+        for l in 0..self.sparse.len() {
+            for r in 0..self.sparse[l].len() {
+                if self.sparse_ring(l, r).is_none() { continue }
+                for net in ["weight_net", "pruned"] {
+                    let entry = value.get_mut(net)
+                        .and_then(|net| net.get_mut(l))
+                        .and_then(|layer| layer.get_mut(r));
+                    if let Some(entry) = entry {
+                        *entry = Value::Array(Vec::new());
+                    }
+                }
+            }
+        }
+    }
+    //and grows back from it on load
+    pub(crate) fn fill_sparse(&mut self) {
+        self.settle_pruned();
+        for l in 0..self.sparse.len() {
+            for r in 0..self.sparse[l].len() {
+                let Some(sparse) = &self.sparse[l][r] else { continue };
+                if self.weight_net[l][r].is_empty() {
+                    self.weight_net[l][r] = sparse.dense();
+                }
+                if self.pruned[l][r].is_empty() {
+                    self.pruned[l][r] = sparse.mask();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    fn batch(count: usize) -> (Vec<Vec<f32>>, Vec<u8>) {
+        let mut rng = StdRng::seed_from_u64(5);
+        let images = (0..count).map(|_| (0..16).map(|_| rng.random::<f32>()).collect()).collect();
+        let labels = (0..count).map(|i| (i % 10) as u8).collect();
+        (images, labels)
+    }
+
+    #[test]
+    fn pruned_model_still_projects() {
+        let mut model = Model::<f32>::new(2, 8, 16);
+        model.cap(10);
+        model.prune(0.9, PruneScope::PerRing);
+        assert!(model.sparse_ring(1, 0).is_some());
+        let (images, _) = batch(12);
+        let mut buffers = ActivationBuffers::new(&model, images.len());
+        let answers = model.projection_batch(&images, &mut buffers);
+        assert!(answers.iter().all(|answer| *answer < 10));
+    }
+
+    #[test]
+    fn sparse_save_drops_the_mask_and_load_rebuilds_it() {
+        let mut model = Model::<f32>::new(2, 8, 16);
+        model.cap(10);
+        model.prune(0.9, PruneScope::PerRing);
+        assert!(model.sparse_ring(1, 0).is_some());
+        let file = std::env::temp_dir().join("sparse_round_trip").to_string_lossy().into_owned();
+        model.save(&file).unwrap();
+        let saved: Value = serde_json::from_reader(std::fs::File::open(format!("{file}.json")).unwrap()).unwrap();
+        assert_eq!(saved["pruned"][1][0], Value::Array(Vec::new()));
+        let loaded = Model::<f32>::load(&file).unwrap();
+        std::fs::remove_file(format!("{file}.json")).unwrap();
+        assert!(loaded.weight_net == model.weight_net);
+        assert!(loaded.pruned == model.pruned);
+    }
+
+    #[test]
+    fn training_leaves_pruned_weights_at_zero() {
+        let mut model = Model::<f32>::new(2, 8, 16);
+        model.cap(10);
+        //dense enough that no ring goes sparse, only the mask holds them
+        model.prune(0.5, PruneScope::PerRing);
+        let zeros: Vec<usize> = (0..model.weight_net[1][0].len()).filter(|i| model.weight_net[1][0][*i] == 0.0).collect();
+        assert!(!zeros.is_empty());
+        assert!(model.sparse_ring(1, 0).is_none());
+
+        let (images, labels) = batch(64);
+        let config = TrainConfig { epochs: 2, threads: 1, validation: 0.0, ..TrainConfig::default() };
+        let before = model.weight_net.clone();
+        model.train(&images, &labels, &config);
+        assert!(model.weight_net != before);
+        assert!(zeros.iter().all(|i| model.weight_net[1][0][*i] == 0.0));
+    }
+}
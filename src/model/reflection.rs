@@ -0,0 +1,258 @@
+use crate::model::*;
+
+//the mirrored trees again, holding how much each weight and bias
+//should move instead of the weights and biases themselves.
+pub struct Gradients<F: Scalar = f32> {
+    pub(crate) weight_net: Funnel<Layer<Weights<F>>>,
+    pub(crate) bias_net: Funnel<Layer<Biases<F>>>,
+    //the same for each normalized ring's scale and shift
+    pub(crate) scale_net: Funnel<Layer<Vec<F>>>,
+    pub(crate) shift_net: Funnel<Layer<Vec<F>>>,
+    //not gradients, the batch statistics batch norm saw.
+    //they ride along the same reduce to feed the running ones
+    pub(crate) mean_net: Funnel<Layer<Vec<F>>>,
+    pub(crate) variance_net: Funnel<Layer<Vec<F>>>,
+}
+
+impl<F: Scalar> Gradients<F> {
+    pub fn new(model: &Model<F>) -> Gradients<F> {
+        let zeroed = |net: &Funnel<Layer<Vec<F>>>| -> Funnel<Layer<Vec<F>>> {
+            net.iter()
+                .map(|layer| layer.iter().map(|entry| vec![F::zero(); entry.len()]).collect::<Vec<_>>())
+                .collect()
+        };
+        let normals = |part: fn(&Normal<F>) -> usize| -> Funnel<Layer<Vec<F>>> {
+            model.norm_net.iter()
+                .map(|layer| layer.iter().map(|normal| vec![F::zero(); part(normal)]).collect::<Vec<_>>())
+                .collect()
+        };
+        Gradients {
+            weight_net: zeroed(&model.weight_net),
+            bias_net: zeroed(&model.bias_net),
+            scale_net: normals(|normal| normal.scale.len()),
+            shift_net: normals(|normal| normal.shift.len()),
+            mean_net: normals(|normal| normal.running_mean.len()),
+            variance_net: normals(|normal| normal.running_variance.len()),
+        }
+    }
+    //folds another shard's gradients into these
+    pub fn add(&mut self, other: &Gradients<F>) {
+        let pairs = [
+            (&mut self.weight_net, &other.weight_net),
+            (&mut self.bias_net, &other.bias_net),
+            (&mut self.scale_net, &other.scale_net),
+            (&mut self.shift_net, &other.shift_net),
+            (&mut self.mean_net, &other.mean_net),
+            (&mut self.variance_net, &other.variance_net),
+        ];
+        for (net, other_net) in pairs {
+            for (layer, other_layer) in net.iter_mut().zip(other_net) {
+                for (entry, other_entry) in layer.iter_mut().zip(other_layer) {
+                    for (value, other_value) in entry.iter_mut().zip(other_entry) {
+                        *value += *other_value;
+                    }
+                }
+            }
+        }
+    }
+    pub fn clear(&mut self) {
+        let nets = [
+            &mut self.weight_net,
+            &mut self.bias_net,
+            &mut self.scale_net,
+            &mut self.shift_net,
+            &mut self.mean_net,
+            &mut self.variance_net,
+        ];
+        for net in nets {
+            for layer in net.iter_mut() {
+                for entry in layer.iter_mut() {
+                    entry.fill(F::zero());
+                }
+            }
+        }
+    }
+}
+
+impl<F: Scalar> Model<F> {
+    //wave through funnel that is reflected back.
+    //one_hot encoding, with our math for error,
+    //is our mirror. backprop
+    //
+    //adds this batch's gradients into `gradients` and returns the summed
+    //softmax cross entropy loss. nothing in the model is touched.
+    pub fn reflection<I: AsRef<[f32]>>(
+        &self,
+        images: &[I],
+        labels: &[u8],
+        buffers: &mut ActivationBuffers<F>,
+        gradients: &mut Gradients<F>,
+        ) -> F {
+        self.reflect(images, labels, None, buffers, gradients)
+    }
+    //soft is a teacher's cap outputs for the same images,
+    //[batch x classes], blended into the mirror
+    pub(crate) fn reflect<I: AsRef<[f32]>>(
+        &self,
+        images: &[I],
+        labels: &[u8],
+        soft: Option<(&[F], &Distillation)>,
+        buffers: &mut ActivationBuffers<F>,
+        gradients: &mut Gradients<F>,
+        ) -> F {
+        let ring_net = &self.ring_net;
+        let batch = images.len();
+        assert_eq!(batch, labels.len());
+        self.forward_batch(images, buffers);
+
+        for layer in buffers.delta_net.iter_mut() {
+            for delta in layer.iter_mut() {
+                delta.fill(F::zero());
+            }
+        }
+
+        //the mirror: softmax of the cap against the one_hot answer
+        let last_layer = ring_net.len() - 1;
+        let classes = ring_net[last_layer][0].neuron_count;
+        let output = &buffers.activation_net[last_layer][0];
+        let delta = &mut buffers.delta_net[last_layer][0];
+        let mut loss = F::zero();
+        for b in 0..batch {
+            let logits = &output[b*classes..(b+1)*classes];
+            let error = &mut delta[b*classes..(b+1)*classes];
+            let answer = labels[b] as usize;
+            assert!(answer < classes, "label {answer} outside of the cap's {classes} neurons");
+            let hard = softmax_into(logits, error, answer);
+            loss += match soft {
+                Some((teacher, distillation)) => distillation.blend(logits, &teacher[b*classes..(b+1)*classes], error, hard),
+                None => hard,
+            };
+        }
+
+        //walk back up the funnel. a ring's delta is complete once
+        //every parent in the later layers has added into it.
+        for l in (1..ring_net.len()).rev() {
+            for r in (0..ring_net[l].len()).rev() {
+                let ring = &ring_net[l][r];
+                let k = Self::gathered_count(ring_net, ring);
+                let n = ring.neuron_count;
+
+                //Relu lets error through only where it let the signal through
+                let delta = &mut buffers.delta_net[l][r][..batch*n];
+                if l != last_layer && ring.kind.has_weights() {
+                    let output = &buffers.activation_net[l][r][..batch*n];
+                    for (d, a) in delta.iter_mut().zip(output) {
+                        if *a <= F::zero() { *d = F::zero() }
+                    }
+                    //dropped outputs are 0 and already stopped above,
+                    //the kept ones were scaled up on the way out
+                    let rate = ring.regularize.dropout;
+                    if buffers.dropout.is_some() && rate > 0.0 {
+                        let keep = F::of_f32(1.0 / (1.0 - rate));
+                        for d in delta.iter_mut() { *d *= keep }
+                    }
+                }
+                let normal = &self.norm_net[l][r];
+                if normal.kind != NormKind::None {
+                    let training = buffers.dropout.is_some();
+                    let norm_buffers = &buffers.norm_net[l][r];
+                    normal.backward(
+                        &mut buffers.delta_net[l][r][..batch*n],
+                        n,
+                        training,
+                        norm_buffers,
+                        &mut gradients.scale_net[l][r],
+                        &mut gradients.shift_net[l][r],
+                    );
+                    if training && normal.kind == NormKind::Batch {
+                        for (sum, mean) in gradients.mean_net[l][r].iter_mut().zip(&norm_buffers.mean) { *sum += *mean }
+                        for (sum, variance) in gradients.variance_net[l][r].iter_mut().zip(&norm_buffers.variance) { *sum += *variance }
+                    }
+                }
+                let delta = &buffers.delta_net[l][r][..batch*n];
+
+                let mut gather = std::mem::take(&mut buffers.gather);
+                let mut gather_delta = std::mem::take(&mut buffers.gather_delta);
+                gather_children(ring_net, &buffers.activation_net, ring, batch, &mut gather);
+                gather_delta[..batch*k].fill(F::zero());
+                match &ring.kind {
+                    RingKind::Pool(pool) => pool.backward(&gather[..batch*k], delta, &mut gather_delta[..batch*k], batch),
+                    RingKind::Identity => gather_delta[..batch*k].copy_from_slice(delta),
+                    RingKind::Conv(conv) => conv.backward(
+                        &gather[..batch*k],
+                        &self.weight_net[l][r],
+                        delta,
+                        //a frozen ring's gradients would only be thrown away
+                        ConvGradient {
+                            weight_gradient: ring.trainable.then_some(&mut gradients.weight_net[l][r][..]),
+                            bias_gradient: ring.trainable.then_some(&mut gradients.bias_net[l][r][..]),
+                            gather_delta: &mut gather_delta[..batch*k],
+                        },
+                        batch,
+                    ),
+                    RingKind::Dense => {
+                        //a frozen ring's gradients would only be thrown away
+                        if ring.trainable {
+                            let bias_gradient = &mut gradients.bias_net[l][r];
+                            for row in delta.chunks_exact(n) {
+                                for (g, d) in bias_gradient.iter_mut().zip(row) {
+                                    *g += *d;
+                                }
+                            }
+                        }
+                        if let Some(sparse) = self.sparse_ring(l, r) {
+                            let weight_gradient = ring.trainable.then_some(&mut gradients.weight_net[l][r][..]);
+                            sparse.backward(delta, &gather[..batch*k], weight_gradient, &mut gather_delta[..batch*k], batch);
+                        } else {
+                            if ring.trainable {
+                                F::gemm_tn(delta, &gather[..batch*k], &mut gradients.weight_net[l][r], batch, n, k);
+                            }
+                            //hand the error back to the children, column block by column block
+                            F::gemm_nn(delta, &self.weight_net[l][r], &mut gather_delta[..batch*k], batch, n, k);
+                        }
+                    },
+                }
+                buffers.gather = gather;
+                scatter_children(ring_net, &buffers.activation_net, &mut buffers.delta_net, ring, batch, &gather_delta);
+                buffers.gather_delta = gather_delta;
+            }
+        }
+        loss
+    }
+}
+
+impl<F: Scalar> Model<F> {
+    //just the mirror's verdict, no way back.
+    //summed cross entropy over the images.
+    pub fn loss<I: AsRef<[f32]>>(&self, images: &[I], labels: &[u8], buffers: &mut ActivationBuffers<F>) -> F {
+        let batch = images.len();
+        self.forward_batch(images, buffers);
+        let last_layer = self.ring_net.len() - 1;
+        let classes = self.ring_net[last_layer][0].neuron_count;
+        let output = &buffers.activation_net[last_layer][0];
+        let mut error = vec![F::zero(); classes];
+        let mut loss = F::zero();
+        for b in 0..batch {
+            let logits = &output[b*classes..(b+1)*classes];
+            loss += softmax_into(logits, &mut error, labels[b] as usize);
+        }
+        loss
+    }
+}
+
+//writes softmax(logits) - one_hot(answer) into error
+//and returns the cross entropy of the answer.
+pub(crate) fn softmax_into<F: Scalar>(logits: &[F], error: &mut [F], answer: usize) -> F {
+    let max = logits.iter().cloned().fold(F::min_value(), F::max);
+    let mut total = F::zero();
+    for (e, z) in error.iter_mut().zip(logits) {
+        *e = (*z - max).exp();
+        total += *e;
+    }
+    for e in error.iter_mut() {
+        *e /= total;
+    }
+    let loss = -(error[answer].max(F::min_positive_value())).ln();
+    error[answer] -= F::one();
+    loss
+}
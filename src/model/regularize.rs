@@ -0,0 +1,115 @@
+use std::fmt;
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::model::*;
+
+//what keeps a ring from memorizing the training set.
+//all of it only acts while training, test and inference never see it.
+#[derive(serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Regularize {
+    //pulls every weight toward 0 by the same amount
+    pub l1: f32,
+    //pulls every weight toward 0 in proportion to its size
+    pub l2: f32,
+    //share of the ring's outputs zeroed on each training image
+    pub dropout: f32,
+    //longest a neuron's weight row may be after a step. 0 is off
+    pub max_norm: f32,
+}
+
+impl Regularize {
+    pub fn is_off(&self) -> bool {
+        *self == Regularize::default()
+    }
+}
+
+impl fmt::Display for Regularize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "l1: {}, l2: {}, dropout: {}, max norm: {}", self.l1, self.l2, self.dropout, self.max_norm)
+    }
+}
+
+//each shard gets its own dropout stream, drawn from where it sits in
+//training and not from which thread ran it. splitmix64 to scatter the bits.
+pub(crate) fn shard_rng(seed: u64, epoch: usize, batch: usize, shard: usize) -> StdRng {
+    let mut x = seed;
+    for part in [epoch as u64, batch as u64, shard as u64] {
+        x = x.wrapping_add(part).wrapping_add(0x9E37_79B9_7F4A_7C15);
+        x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        x ^= x >> 31;
+    }
+    StdRng::seed_from_u64(x)
+}
+
+//inverted dropout, the kept outputs grow so the
+//ring's expected output is the same with it off
+pub(crate) fn drop_out<F: Scalar>(output: &mut [F], rate: f32, rng: &mut StdRng) {
+    let keep = F::of_f32(1.0 / (1.0 - rate));
+    for value in output.iter_mut() {
+        if rng.random::<f32>() < rate {
+            *value = F::zero();
+        } else {
+            *value *= keep;
+        }
+    }
+}
+
+impl<F: Scalar> Model<F> {
+    pub fn regularize(&self, layer: usize, ring: usize) -> Option<Regularize> {
+        self.ring_net.get(layer)?.get(ring).map(|ring| ring.regularize)
+    }
+    pub fn set_regularize(&mut self, layer: usize, ring: usize, regularize: Regularize) {
+        if layer == 0 || self.regularize(layer, ring).is_none() {
+            println!(">no ring at [{layer}][{ring}]");
+            return
+        }
+        if !self.ring_net[layer][ring].kind.has_weights() {
+            println!(">[{layer}][{ring}] has nothing to regularize");
+            return
+        }
+        let mut regularize = regularize;
+        //dropping everything would leave nothing to scale back up
+        regularize.dropout = regularize.dropout.clamp(0.0, 0.95);
+        self.ring_net[layer][ring].regularize = regularize;
+    }
+
+    //the penalties' pull, taken with the full learning rate
+    //since they belong to the weights and not to any image
+    pub(crate) fn penalize(&mut self, rate: F) {
+        for (l, layer) in self.ring_net.iter().enumerate() {
+            for (r, ring) in layer.iter().enumerate() {
+                let Regularize { l1, l2, .. } = ring.regularize;
+                if !ring.trainable || (l1 == 0.0 && l2 == 0.0) { continue }
+                let (l1, l2) = (F::of_f32(l1), F::of_f32(l2));
+                for weight in self.weight_net[l][r].iter_mut() {
+                    let sign = if *weight == F::zero() { F::zero() } else { weight.signum() };
+                    let pull = l2 * *weight + l1 * sign;
+                    *weight -= rate * pull;
+                }
+            }
+        }
+    }
+
+    //scales any neuron's weight row back onto the max norm
+    pub(crate) fn clip_norms(&mut self) {
+        for (l, layer) in self.ring_net.iter().enumerate() {
+            for (r, ring) in layer.iter().enumerate() {
+                let max_norm = ring.regularize.max_norm;
+                if !ring.trainable || max_norm <= 0.0 { continue }
+                let k = Self::source_activations_count(&self.ring_net, ring);
+                if k == 0 { continue }
+                let max_norm = F::of_f32(max_norm);
+                for row in self.weight_net[l][r].chunks_exact_mut(k) {
+                    let norm = row.iter().map(|w| *w * *w).sum::<F>().sqrt();
+                    if norm > max_norm {
+                        let shrink = max_norm / norm;
+                        for w in row.iter_mut() { *w *= shrink }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use std::fmt::{Debug, Display};
+use std::iter::Sum;
+use std::ops::{AddAssign, DivAssign, MulAssign, SubAssign};
+
+use num_traits::Float;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::model::*;
+use crate::model::kernels;
+
+//the number a Model is built out of.
+//f32 is the everyday one and gets the simd kernels.
+//f64 is for checking gradients and anything numerically touchy.
+//
+//the names are of_f32/as_f32 because num_traits already
+//hands Float a from_f32/to_f32 that return Options.
+pub trait Scalar:
+    Float
+    + AddAssign + SubAssign + MulAssign + DivAssign
+    + Sum
+    + Default + Debug + Display
+    + Send + Sync + 'static
+    + Serialize + DeserializeOwned
+{
+    const NAME: &'static str;
+    fn of_f32(value: f32) -> Self;
+    fn as_f32(self) -> f32;
+    fn gemm_nt(input: &[Self], weights: &[Self], out: &mut [Self], m: usize, k: usize, n: usize);
+    fn gemm_nn(delta: &[Self], weights: &[Self], out: &mut [Self], m: usize, n: usize, k: usize);
+    fn gemm_tn(delta: &[Self], input: &[Self], out: &mut [Self], m: usize, n: usize, k: usize);
+}
+
+impl Scalar for f32 {
+    const NAME: &'static str = "f32";
+    fn of_f32(value: f32) -> f32 { value }
+    fn as_f32(self) -> f32 { self }
+    fn gemm_nt(input: &[f32], weights: &[f32], out: &mut [f32], m: usize, k: usize, n: usize) {
+        kernels::gemm_nt(input, weights, out, m, k, n)
+    }
+    fn gemm_nn(delta: &[f32], weights: &[f32], out: &mut [f32], m: usize, n: usize, k: usize) {
+        kernels::gemm_nn(delta, weights, out, m, n, k)
+    }
+    fn gemm_tn(delta: &[f32], input: &[f32], out: &mut [f32], m: usize, n: usize, k: usize) {
+        kernels::gemm_tn(delta, input, out, m, n, k)
+    }
+}
+
+impl Scalar for f64 {
+    const NAME: &'static str = "f64";
+    fn of_f32(value: f32) -> f64 { value as f64 }
+    fn as_f32(self) -> f32 { self as f32 }
+    fn gemm_nt(input: &[f64], weights: &[f64], out: &mut [f64], m: usize, k: usize, n: usize) {
+        kernels::generic::gemm_nt(input, weights, out, m, k, n)
+    }
+    fn gemm_nn(delta: &[f64], weights: &[f64], out: &mut [f64], m: usize, n: usize, k: usize) {
+        kernels::generic::gemm_nn(delta, weights, out, m, n, k)
+    }
+    fn gemm_tn(delta: &[f64], input: &[f64], out: &mut [f64], m: usize, n: usize, k: usize) {
+        kernels::generic::gemm_tn(delta, input, out, m, n, k)
+    }
+}
+
+impl<F: Scalar> Model<F> {
+    //the same model in another number type.
+    //going down to f32 rounds, going up to f64 is exact.
+    pub fn convert<G: Scalar>(&self) -> Model<G> {
+        Model {
+            ring_net: self.ring_net.clone(),
+            weight_net: convert_net(&self.weight_net),
+            bias_net: convert_net(&self.bias_net),
+            norm_net: self.norm_net.iter()
+                .map(|layer| layer.iter().map(|normal| normal.convert()).collect())
+                .collect(),
+            state: self.state.clone(),
+            quantized: self.quantized.clone(),
+            preprocess: self.preprocess.clone(),
+            progress: self.progress.clone(),
+            sparse: self.sparse.iter()
+                .map(|layer| layer.iter().map(|sparse| sparse.as_ref().map(SparseRing::convert)).collect())
+                .collect(),
+            pruned: self.pruned.clone(),
+            split: self.split,
+        }
+    }
+}
+
+pub(crate) fn convert_net<F: Scalar, G: Scalar>(net: &Funnel<Layer<Vec<F>>>) -> Funnel<Layer<Vec<G>>> {
+    net.iter().map(|layer| {
+        layer.iter().map(|entry| {
+            entry.iter().map(|value| <G as num_traits::NumCast>::from(*value).unwrap()).collect()
+        }).collect()
+    }).collect()
+}
@@ -0,0 +1,105 @@
+use std::f32::consts::PI;
+use std::fmt;
+
+use crate::model::*;
+
+//one-cycle climbs from rate / ONE_CYCLE_START for this share of its length
+const ONE_CYCLE_RISE: f32 = 0.3;
+const ONE_CYCLE_START: f32 = 25.0;
+//and settles at rate / ONE_CYCLE_END
+const ONE_CYCLE_END: f32 = 1e4;
+
+//how the learning rate moves over the epochs.
+//the train config's rate is the base every schedule scales.
+#[derive(serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum Schedule {
+    #[default]
+    Constant,
+    //times factor every `every` epochs
+    Step { every: usize, factor: f32 },
+    //times factor each epoch, smoothly
+    Exponential { factor: f32 },
+    //half a cosine from the base down to base * floor, then again
+    Cosine { period: usize, floor: f32 },
+    //up then down once over `length` epochs, the base is the peak
+    OneCycle { length: usize },
+}
+
+//where a model is in its schedule. kept in the model,
+//so a checkpoint picks up the rate where it left off.
+#[derive(serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, Default)]
+pub struct Progress {
+    pub schedule: Schedule,
+    //linear ramp from 0 up to the scheduled rate, in epochs
+    pub warmup: usize,
+    //epochs trained under this schedule
+    pub epoch: usize,
+}
+
+impl Progress {
+    //the rate at a point in training, counted in epochs.
+    //fractions come from the batches inside an epoch.
+    pub fn rate(&self, base: f32, at: f32) -> f32 {
+        let warmup = self.warmup as f32;
+        if at < warmup {
+            return base * at / warmup * self.schedule.factor(0.0)
+        }
+        base * self.schedule.factor(at - warmup)
+    }
+}
+
+impl Schedule {
+    fn factor(&self, at: f32) -> f32 {
+        match *self {
+            Schedule::Constant => 1.0,
+            Schedule::Step { every, factor } => factor.powi((at / every.max(1) as f32).floor() as i32),
+            Schedule::Exponential { factor } => factor.powf(at),
+            Schedule::Cosine { period, floor } => {
+                let period = period.max(1) as f32;
+                let cycle = at % period / period;
+                floor + (1.0 - floor) * 0.5 * (1.0 + (PI * cycle).cos())
+            },
+            Schedule::OneCycle { length } => {
+                let done = (at / length.max(1) as f32).min(1.0);
+                if done < ONE_CYCLE_RISE {
+                    let start = 1.0 / ONE_CYCLE_START;
+                    start + (1.0 - start) * done / ONE_CYCLE_RISE
+                } else {
+                    let end = 1.0 / ONE_CYCLE_END;
+                    let fall = (done - ONE_CYCLE_RISE) / (1.0 - ONE_CYCLE_RISE);
+                    end + (1.0 - end) * 0.5 * (1.0 + (PI * fall).cos())
+                }
+            },
+        }
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Schedule::Constant => write!(f, "constant"),
+            Schedule::Step { every, factor } => write!(f, "step x{factor} every {every}"),
+            Schedule::Exponential { factor } => write!(f, "exponential x{factor}"),
+            Schedule::Cosine { period, floor } => write!(f, "cosine to x{floor} every {period}"),
+            Schedule::OneCycle { length } => write!(f, "one-cycle over {length}"),
+        }
+    }
+}
+
+impl fmt::Display for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "schedule: {}, warmup: {}, at epoch {}", self.schedule, self.warmup, self.epoch)
+    }
+}
+
+impl<F: Scalar> Model<F> {
+    pub fn progress(&self) -> &Progress {
+        &self.progress
+    }
+    //a new schedule starts from its first epoch
+    pub fn set_schedule(&mut self, schedule: Schedule, warmup: usize) {
+        self.progress = Progress { schedule, warmup, epoch: 0 };
+    }
+}
@@ -0,0 +1,223 @@
+use crate::model::*;
+
+//how much a neuron is worth keeping
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NeuronRank {
+    //the summed magnitude of every weight that reads it
+    Weight,
+    //how loud it is on average over a sample of images
+    Activation,
+}
+
+impl std::str::FromStr for NeuronRank {
+    type Err = ();
+    fn from_str(s: &str) -> Result<NeuronRank, ()> {
+        match s {
+            "weight" | "weights" => Ok(NeuronRank::Weight),
+            "activation" | "activations" => Ok(NeuronRank::Activation),
+            _ => Err(()),
+        }
+    }
+}
+
+impl<F: Scalar> Model<F> {
+    //removes the weakest share of each hidden dense ring's neurons,
+    //their weight rows and biases, and the columns that read them
+    //in every parent. every ring keeps at least one neuron.
+    //
+    //a ring is left as it is if it is frozen, or if a parent can't
+    //lose columns: conv, pool and identity parents read it by
+    //position, and the elementwise merges need it as wide as its siblings.
+    pub fn prune_neurons(&mut self, share: f32, rank: NeuronRank, sample: &[Vec<f32>]) {
+        if let ModelState::Malformed = self.state {
+            println!(">model is not capped");
+            return
+        }
+        let share = share.clamp(0.0, 1.0);
+        let last_layer = self.ring_net.len() - 1;
+        let mut rings = Vec::new();
+        for l in 1..last_layer {
+            for r in 0..self.ring_net[l].len() {
+                let ring = &self.ring_net[l][r];
+                if ring.kind != RingKind::Dense || !ring.trainable { continue }
+                let parents = self.parents(l, r);
+                let open = parents.iter().all(|(pl, pr)| {
+                    let parent = &self.ring_net[*pl][*pr];
+                    parent.kind == RingKind::Dense && parent.merge == Merge::Concat
+                });
+                if !open {
+                    println!(">[{l}][{r}] feeds a ring that reads it by position, kept whole");
+                    continue
+                }
+                rings.push((l, r));
+            }
+        }
+
+        //every score is taken before anything shrinks
+        let activity = match rank {
+            NeuronRank::Activation => Some(self.mean_activations(sample)),
+            NeuronRank::Weight => None,
+        };
+        let mut plans = Vec::new();
+        for (l, r) in rings {
+            let n = self.ring_net[l][r].neuron_count;
+            let scores = match &activity {
+                Some(activity) => activity[l][r].clone(),
+                None => self.outgoing_magnitudes(l, r),
+            };
+            let remove = ((n as f32 * share) as usize).min(n - 1);
+            if remove == 0 { continue }
+            let mut order: Vec<usize> = (0..n).collect();
+            order.sort_by(|a, b| scores[*b].total_cmp(&scores[*a]));
+            let mut keep = order[..n - remove].to_vec();
+            keep.sort();
+            plans.push((l, r, keep));
+        }
+
+        let (_, before) = self.total_parameter_count();
+        self.quantized = None;
+        for (l, r, keep) in &plans {
+            println!(">[{l}][{r}] {} -> {} neurons", self.ring_net[*l][*r].neuron_count, keep.len());
+            self.shrink_ring(*l, *r, keep);
+        }
+        self.settle_sparse();
+        let (_, after) = self.total_parameter_count();
+        println!(">{} rings shrunk, {before} -> {after} parameters", plans.len());
+    }
+
+    //every ring that lists [l][r] as a child
+    fn parents(&self, layer: usize, ring: usize) -> Vec<(usize, usize)> {
+        let mut parents = Vec::new();
+        for l in layer + 1..self.ring_net.len() {
+            for r in 0..self.ring_net[l].len() {
+                if self.ring_net[l][r].children.iter().any(|child| child.0 == layer && child.1 == ring) {
+                    parents.push((l, r));
+                }
+            }
+        }
+        parents
+    }
+    //where [l][r]'s neurons start in a concat parent's weight rows,
+    //once for every time the parent lists it
+    fn column_offsets(&self, parent: (usize, usize), layer: usize, ring: usize) -> Vec<usize> {
+        let mut offsets = Vec::new();
+        let mut offset = 0;
+        for child in &self.ring_net[parent.0][parent.1].children {
+            if child.0 == layer && child.1 == ring { offsets.push(offset) }
+            offset += self.ring_net[child.0][child.1].neuron_count;
+        }
+        offsets
+    }
+
+    fn outgoing_magnitudes(&self, layer: usize, ring: usize) -> Vec<f32> {
+        let mut scores = vec![0f32; self.ring_net[layer][ring].neuron_count];
+        for (pl, pr) in self.parents(layer, ring) {
+            let k = Self::source_activations_count(&self.ring_net, &self.ring_net[pl][pr]);
+            for offset in self.column_offsets((pl, pr), layer, ring) {
+                for row in self.weight_net[pl][pr].chunks_exact(k) {
+                    for (score, w) in scores.iter_mut().zip(&row[offset..]) {
+                        *score += w.as_f32().abs();
+                    }
+                }
+            }
+        }
+        scores
+    }
+    fn mean_activations(&self, sample: &[Vec<f32>]) -> Funnel<Layer<Vec<f32>>> {
+        let mut sums: Funnel<Layer<Vec<f32>>> = self.ring_net.iter()
+            .map(|layer| layer.iter().map(|ring| vec![0f32; ring.neuron_count]).collect())
+            .collect();
+        let mut buffers = ActivationBuffers::new(self, TEST_BATCH);
+        for images in sample.chunks(TEST_BATCH) {
+            self.forward_batch(images, &mut buffers);
+            for (l, layer) in self.ring_net.iter().enumerate() {
+                for (r, ring) in layer.iter().enumerate() {
+                    let n = ring.neuron_count;
+                    for row in buffers.activation_net[l][r][..images.len() * n].chunks_exact(n) {
+                        for (sum, a) in sums[l][r].iter_mut().zip(row) {
+                            *sum += a.as_f32();
+                        }
+                    }
+                }
+            }
+        }
+        let count = sample.len().max(1) as f32;
+        for sum in sums.iter_mut().flatten().flatten() {
+            *sum /= count;
+        }
+        sums
+    }
+
+    //keep is sorted, the neurons stay in their order
+    fn shrink_ring(&mut self, layer: usize, ring: usize, keep: &[usize]) {
+        let n = self.ring_net[layer][ring].neuron_count;
+        let k = Self::source_activations_count(&self.ring_net, &self.ring_net[layer][ring]);
+
+        //the parents first, their offsets need the old width
+        for (pl, pr) in self.parents(layer, ring) {
+            let parent_k = Self::source_activations_count(&self.ring_net, &self.ring_net[pl][pr]);
+            let mut dropped = vec![false; parent_k];
+            for offset in self.column_offsets((pl, pr), layer, ring) {
+                dropped[offset..offset + n].fill(true);
+                for j in keep { dropped[offset + j] = false }
+            }
+            let weight_count = self.weight_net[pl][pr].len();
+            self.weight_net[pl][pr] = drop_columns(&self.weight_net[pl][pr], parent_k, &dropped);
+            //a prune mask shrinks along with what it covers
+            let mask = self.pruned.get_mut(pl).and_then(|masks| masks.get_mut(pr));
+            if let Some(mask) = mask.filter(|mask| mask.len() == weight_count) {
+                *mask = drop_columns(mask, parent_k, &dropped);
+            }
+        }
+
+        let rows = |values: &Vec<F>, width: usize| -> Vec<F> {
+            keep.iter().flat_map(|j| values[j*width..(j+1)*width].iter().cloned()).collect()
+        };
+        let weight_count = self.weight_net[layer][ring].len();
+        let mask = self.pruned.get_mut(layer).and_then(|masks| masks.get_mut(ring));
+        if let Some(mask) = mask.filter(|mask| mask.len() == weight_count) {
+            *mask = keep.iter().flat_map(|j| mask[j*k..(j+1)*k].to_vec()).collect();
+        }
+        self.weight_net[layer][ring] = rows(&self.weight_net[layer][ring], k);
+        self.bias_net[layer][ring] = rows(&self.bias_net[layer][ring], 1);
+        let normal = &mut self.norm_net[layer][ring];
+        for values in [&mut normal.scale, &mut normal.shift, &mut normal.running_mean, &mut normal.running_variance] {
+            if !values.is_empty() { *values = rows(values, 1) }
+        }
+        self.ring_net[layer][ring].neuron_count = keep.len();
+    }
+}
+
+//every row of width k without the dropped columns
+fn drop_columns<T: Copy>(values: &[T], k: usize, dropped: &[bool]) -> Vec<T> {
+    values.chunks_exact(k)
+        .flat_map(|row| row.iter().zip(dropped).filter(|(_, dropped)| !**dropped).map(|(v, _)| *v))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    #[test]
+    fn shrunk_model_still_projects() {
+        let mut rng = StdRng::seed_from_u64(9);
+        let images: Vec<Vec<f32>> = (0..20).map(|_| (0..16).map(|_| rng.random::<f32>()).collect()).collect();
+        for rank in [NeuronRank::Weight, NeuronRank::Activation] {
+            let mut model = Model::<f32>::new(2, 8, 16);
+            model.cap(10);
+            model.prune(0.5, PruneScope::PerRing);
+            model.prune_neurons(0.5, rank, &images);
+            assert_eq!(model.ring_net[1][0].neuron_count, 4);
+            assert_eq!(model.ring_net[2][0].neuron_count, 4);
+            let mut buffers = ActivationBuffers::new(&model, images.len());
+            let answers = model.projection_batch(&images, &mut buffers);
+            assert!(answers.iter().all(|answer| *answer < 10));
+            let single: Vec<usize> = images.iter().map(|image| model.projection(image)).collect();
+            assert_eq!(single, answers);
+            //the masks shrank with the weights they cover
+            assert_eq!(model.pruned[2][0].len(), model.weight_net[2][0].len());
+        }
+    }
+}
@@ -0,0 +1,375 @@
+use std::fmt;
+use std::time::Instant;
+
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+use rayon::prelude::*;
+
+use crate::model::*;
+use crate::dataset::Augment;
+
+//images per shard. fixed no matter how many threads there are,
+//so the shards, and the order their gradients are folded together,
+//are the same with 1 thread or 32. that is what keeps a seeded
+//run identical across thread counts.
+const SHARD: usize = 16;
+
+#[derive(Debug, Clone)]
+pub struct TrainConfig {
+    pub epochs: usize,
+    pub batch_size: usize,
+    pub learning_rate: f32,
+    pub threads: usize,
+    pub seed: u64,
+    //share of the training data held back, per label, to judge each epoch
+    pub validation: f32,
+    //epochs without a better validation loss before stopping. 0 never stops
+    pub patience: usize,
+    //where the best model so far is written, "best" unless
+    //turned off. it is always kept in memory either way
+    pub best_file: Option<String>,
+    //random variants of each training image, off by default
+    pub augment: Augment,
+    //only used when a teacher is loaded
+    pub distill: Distillation,
+}
+
+impl Default for TrainConfig {
+    fn default() -> TrainConfig {
+        TrainConfig {
+            epochs: 1,
+            batch_size: 64,
+            learning_rate: 0.01,
+            threads: std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+            seed: 0,
+            validation: 0.1,
+            patience: 3,
+            best_file: Some(String::from("best")),
+            augment: Augment::default(),
+            distill: Distillation::default(),
+        }
+    }
+}
+
+//the images a model's training held back, kept in the model
+//so they can be found again once it is saved
+#[derive(serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Split {
+    //nothing learned yet, every image is unseen
+    Untrained,
+    Held { seed: u64, validation: f32 },
+    //trained under more than one split, or saved before they were kept
+    #[default]
+    Unknown,
+}
+
+impl Split {
+    //the same seed and share always hold back the same images,
+    //so a model trained under this split never learned from them
+    pub fn held_back(&self, labels: &[u8]) -> Vec<usize> {
+        let Split::Held { seed, validation } = *self else { return Vec::new() };
+        let mut rng = StdRng::seed_from_u64(seed);
+        stratified_split(labels, validation, &mut rng).1
+    }
+}
+
+impl fmt::Display for Split {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Split::Untrained => write!(f, "untrained"),
+            Split::Held { seed, validation } => write!(f, "seed {seed}, validation {validation}"),
+            Split::Unknown => write!(f, "unknown split"),
+        }
+    }
+}
+
+impl fmt::Display for TrainConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f,
+            "epochs: {}, batch: {}, rate: {}, threads: {}, seed: {}, validation: {}, patience: {}, best: {}, augment: {}",
+            self.epochs,
+            self.batch_size,
+            self.learning_rate,
+            self.threads,
+            self.seed,
+            self.validation,
+            self.patience,
+            self.best_file.as_ref().map_or(String::from("not saved"), |file| format!("{file}.json")),
+            self.augment,
+        )
+    }
+}
+
+impl<F: Scalar> Model<F> {
+    //mini-batch gradient descent.
+    //each batch is cut into shards, every worker reflects its shard
+    //against the same read-only weights, then the shard gradients
+    //are summed in order and applied once.
+    pub fn train(&mut self, data: &[Vec<f32>], labels: &[u8], config: &TrainConfig) {
+        self.train_from(data, labels, config, None)
+    }
+    //with a teacher each shard also runs it forward,
+    //and its answers join the labels at the cap
+    pub(crate) fn train_from(
+        &mut self,
+        data: &[Vec<f32>],
+        labels: &[u8],
+        config: &TrainConfig,
+        teacher: Option<&Model<F>>,
+        ) {
+        if let ModelState::Malformed = self.state {
+            println!(">model is not capped");
+            return
+        }
+        if self.quantized.take().is_some() {
+            println!(">dropping int8 weights, training runs in f32");
+        }
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(config.threads.max(1))
+            .build();
+        let pool = match pool {
+            Ok(pool) => pool,
+            Err(e) => { println!(">failed to start {} threads: {e}", config.threads); return },
+        };
+        println!(">training with {config}");
+        println!(">{}", self.progress);
+        //a second split would show it images the first held back
+        let split = Split::Held { seed: config.seed, validation: config.validation };
+        self.split = match self.split {
+            Split::Untrained => split,
+            held if held == split => split,
+            _ => Split::Unknown,
+        };
+        let frozen = self.frozen_count();
+        if frozen > 0 {
+            println!(">{frozen} frozen rings keep their weights");
+        }
+
+        //drawn first, so held_back finds the same split
+        let mut rng = StdRng::seed_from_u64(config.seed);
+        let (mut order, held_back) = stratified_split(labels, config.validation, &mut rng);
+        println!(">{} training, {} validation", order.len(), held_back.len());
+
+        let mut total = Gradients::new(self);
+        let mut best: Option<(f32, Model<F>)> = None;
+        let mut waited = 0;
+        let batch_size = config.batch_size.max(1);
+        let batches = order.len().div_ceil(batch_size).max(1) as f32;
+        for _ in 0..config.epochs {
+            let start = Instant::now();
+            let epoch = self.progress.epoch;
+            let first_rate = self.progress.rate(config.learning_rate, epoch as f32 + 1.0 / batches);
+            order.shuffle(&mut rng);
+            let mut loss = F::zero();
+            for (b, batch) in order.chunks(batch_size).enumerate() {
+                //drawn here on the one seeded rng, not in the workers,
+                //so an augmented run is still the same on any thread count
+                let augmented: Vec<Vec<f32>>;
+                let images: Vec<&Vec<f32>> = if config.augment.is_active() {
+                    augmented = batch.iter().map(|&i| config.augment.apply(&data[i], &mut rng)).collect();
+                    augmented.iter().collect()
+                } else {
+                    batch.iter().map(|&i| &data[i]).collect()
+                };
+                let answers: Vec<u8> = batch.iter().map(|&i| labels[i]).collect();
+
+                total.clear();
+                let model = &*self;
+                let shards: Vec<(F, Gradients<F>)> = pool.install(|| {
+                    images.par_chunks(SHARD)
+                        .zip(answers.par_chunks(SHARD))
+                        .enumerate()
+                        .map_init(
+                            || (ActivationBuffers::new(model, SHARD), teacher.map(|teacher| ActivationBuffers::new(teacher, SHARD))),
+                            |(buffers, teacher_buffers), (shard, (images, answers))| {
+                                buffers.dropout = Some(shard_rng(config.seed, epoch, b, shard));
+                                let mut gradients = Gradients::new(model);
+                                let soft = match (teacher, teacher_buffers.as_mut()) {
+                                    (Some(teacher), Some(teacher_buffers)) => Some(teacher.logits(images, teacher_buffers)),
+                                    _ => None,
+                                };
+                                let soft = soft.as_deref().map(|logits| (logits, &config.distill));
+                                let loss = model.reflect(images, answers, soft, buffers, &mut gradients);
+                                (loss, gradients)
+                            })
+                        .collect()
+                });
+                for (shard_loss, gradients) in &shards {
+                    loss += *shard_loss;
+                    total.add(gradients);
+                }
+                //the rate after this batch, so warmup never steps by 0
+                let rate = self.progress.rate(config.learning_rate, epoch as f32 + (b + 1) as f32 / batches);
+                self.descend(&total, F::of_f32(rate / batch.len() as f32));
+                self.track_statistics(&total, shards.len());
+                self.penalize(F::of_f32(rate));
+                self.clip_norms();
+                self.refresh_sparse();
+            }
+            self.progress.epoch += 1;
+            let train_loss = loss.as_f32() / order.len().max(1) as f32;
+            if held_back.is_empty() {
+                println!(">epoch {}: rate {:e}, loss {:.4} ({:?})", epoch + 1, first_rate, train_loss, start.elapsed());
+                continue
+            }
+
+            let (validation_loss, accuracy) = self.evaluate(data, labels, &held_back);
+            println!(
+                ">epoch {}: rate {:e}, loss {:.4}, validation loss {:.4}, validation accuracy {:.2}% ({:?})",
+                epoch + 1,
+                first_rate,
+                train_loss,
+                validation_loss,
+                accuracy * 100.0,
+                start.elapsed(),
+            );
+            let improved = match &best {
+                Some((best_loss, _)) => validation_loss < *best_loss,
+                None => true,
+            };
+            if improved {
+                waited = 0;
+                best = Some((validation_loss, self.clone()));
+                if let Some(file) = &config.best_file {
+                    match self.save(file) {
+                        Ok(()) => println!(">best so far, saved to {file}.json"),
+                        Err(e) => println!(">failed to save best model: {e}"),
+                    }
+                }
+            } else {
+                waited += 1;
+                if config.patience > 0 && waited >= config.patience {
+                    println!(">no better validation loss in {waited} epochs, stopping");
+                    break
+                }
+            }
+        }
+        //finish on the best epoch's weights, not the last one's.
+        //the epochs run still count, the schedule carries on from them
+        if let Some((best_loss, model)) = best {
+            println!(">keeping the model with validation loss {best_loss:.4}");
+            let epoch = self.progress.epoch;
+            *self = model;
+            self.progress.epoch = epoch;
+        }
+    }
+
+    //mean loss and accuracy over the chosen indices
+    fn evaluate(&self, data: &[Vec<f32>], labels: &[u8], indices: &[usize]) -> (f32, f32) {
+        let mut buffers = ActivationBuffers::new(self, TEST_BATCH);
+        let last_layer = self.ring_net.len() - 1;
+        let classes = self.ring_net[last_layer][0].neuron_count;
+        let mut loss = F::zero();
+        let mut correct = 0usize;
+        for chunk in indices.chunks(TEST_BATCH) {
+            let images: Vec<&Vec<f32>> = chunk.iter().map(|&i| &data[i]).collect();
+            let answers: Vec<u8> = chunk.iter().map(|&i| labels[i]).collect();
+            loss += self.loss(&images, &answers, &mut buffers);
+            let output = &buffers.activation_net[last_layer][0];
+            for (b, answer) in answers.iter().enumerate() {
+                if argmax(&output[b*classes..(b+1)*classes]) == *answer as usize {
+                    correct += 1;
+                }
+            }
+        }
+        let count = indices.len().max(1) as f32;
+        (loss.as_f32() / count, correct as f32 / count)
+    }
+
+    //one step down the slope. pruned weights stay where they are
+    fn descend(&mut self, gradients: &Gradients<F>, rate: F) {
+        let pairs = [
+            (&mut self.weight_net, &gradients.weight_net, Some(&self.pruned)),
+            (&mut self.bias_net, &gradients.bias_net, None),
+        ];
+        let ring_net = &self.ring_net;
+        for (net, gradient_net, masks) in pairs {
+            for (l, (layer, gradient_layer)) in net.iter_mut().zip(gradient_net).enumerate() {
+                for (r, (entry, gradient_entry)) in layer.iter_mut().zip(gradient_layer).enumerate() {
+                    if !ring_net[l][r].trainable { continue }
+                    //a mask that no longer fits is from before the ring changed shape
+                    let mask = masks.and_then(|masks| masks.get(l)?.get(r)).filter(|mask| mask.len() == entry.len());
+                    for (i, (value, gradient)) in entry.iter_mut().zip(gradient_entry).enumerate() {
+                        if mask.is_some_and(|mask| mask[i]) { continue }
+                        *value -= rate * *gradient;
+                    }
+                }
+            }
+        }
+        for (l, layer) in self.norm_net.iter_mut().enumerate() {
+            for (r, normal) in layer.iter_mut().enumerate() {
+                if !ring_net[l][r].trainable { continue }
+                let pairs = [
+                    (&mut normal.scale, &gradients.scale_net[l][r]),
+                    (&mut normal.shift, &gradients.shift_net[l][r]),
+                ];
+                for (values, gradient_values) in pairs {
+                    for (value, gradient) in values.iter_mut().zip(gradient_values) {
+                        *value -= rate * *gradient;
+                    }
+                }
+            }
+        }
+    }
+}
+
+//holds back the same share of every label, so a rare class
+//doesn't vanish from one side of the split.
+//returns (training indices, validation indices).
+fn stratified_split(labels: &[u8], fraction: f32, rng: &mut StdRng) -> (Vec<usize>, Vec<usize>) {
+    let fraction = fraction.clamp(0.0, 1.0);
+    let mut by_label: Vec<Vec<usize>> = vec![Vec::new(); 256];
+    for (i, label) in labels.iter().enumerate() {
+        by_label[*label as usize].push(i);
+    }
+    let mut training = Vec::new();
+    let mut validation = Vec::new();
+    for mut group in by_label {
+        group.shuffle(rng);
+        let held = (group.len() as f32 * fraction).round() as usize;
+        validation.extend_from_slice(&group[..held]);
+        training.extend_from_slice(&group[held..]);
+    }
+    (training, validation)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn seeded_run_ignores_thread_count() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let data: Vec<Vec<f32>> = (0..100).map(|_| (0..16).map(|_| rng.random::<f32>()).collect()).collect();
+        let labels: Vec<u8> = (0..100).map(|i| (i % 3) as u8).collect();
+        let mut model = Model::<f32>::new(2, 8, 16);
+        model.cap(3);
+
+        let config = TrainConfig { epochs: 2, batch_size: 40, threads: 1, seed: 7, validation: 0.0, ..TrainConfig::default() };
+        let mut single = model.clone();
+        single.train(&data, &labels, &config);
+        let mut pooled = model.clone();
+        pooled.train(&data, &labels, &TrainConfig { threads: 4, ..config });
+        assert!(single.weight_net != model.weight_net);
+        assert!(single.weight_net == pooled.weight_net);
+        assert!(single.bias_net == pooled.bias_net);
+    }
+
+    #[test]
+    fn a_second_split_leaves_the_held_back_images_unknown() {
+        let data: Vec<Vec<f32>> = (0..20).map(|i| vec![i as f32 / 20.0; 16]).collect();
+        let labels: Vec<u8> = (0..20).map(|i| (i % 2) as u8).collect();
+        let mut model = Model::<f32>::new(1, 4, 16);
+        model.cap(2);
+        assert_eq!(model.split(), Split::Untrained);
+
+        let config = TrainConfig { threads: 1, seed: 7, validation: 0.0, ..TrainConfig::default() };
+        model.train(&data, &labels, &config);
+        model.train(&data, &labels, &config);
+        assert_eq!(model.split(), Split::Held { seed: 7, validation: 0.0 });
+        model.train(&data, &labels, &TrainConfig { seed: 8, ..config });
+        assert_eq!(model.split(), Split::Unknown);
+        assert!(model.split().held_back(&labels).is_empty());
+    }
+}